@@ -7,6 +7,7 @@ use tokio_util::io::StreamReader;
 use futures_util::{Stream, TryStreamExt};
 
 use crate::{
+    attachments::S3Backend,
     Result, State, extractors::{Admin, Auth}
 };
 
@@ -32,6 +33,11 @@ async fn upload_attachment(
         return Err(crate::error::Error::ServerMisconfiguration);
     }
     let dest_rel_path = params.path.clone().unwrap_or_else(|| ".".to_string());
+
+    if let Some(s3) = &state.attachment_service.s3 {
+        return upload_attachment_s3(&state, s3, &dest_rel_path, multipart).await;
+    }
+
     let mut results = Vec::new();
     while let Some(field) = multipart.next_field().await.unwrap() {
         let name = field.name().unwrap().to_string();
@@ -59,6 +65,54 @@ async fn upload_attachment(
     Ok((StatusCode::OK, Json(results)))
 }
 
+/// S3-backed upload path: `dest_rel_path` is the challenge's `public_id` (the same convention
+/// the CLI uploader already uses when it POSTs here with `?path=<chall_id>`). Each field streams
+/// straight into the bucket under `<public_id>/<filename>`, and the resulting object metadata is
+/// merged into that challenge's `attachments` JSON column so the competitor-facing download
+/// handler can tell a missing key from a missing file.
+async fn upload_attachment_s3(
+    state: &State,
+    s3: &S3Backend,
+    public_id: &str,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<Vec<UploadResult>>)> {
+    let mut results = Vec::new();
+    let mut recorded = serde_json::Map::new();
+
+    while let Some(field) = multipart.next_field().await.unwrap() {
+        let name = field.name().unwrap().to_string();
+        let content_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+        let key = S3Backend::object_key(public_id, &name);
+
+        let stream = field.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+        let meta = s3
+            .put_object_stream(&key, &content_type, stream)
+            .await
+            .map_err(|_| crate::error::Error::ServerMisconfiguration)?;
+
+        let presigned = s3
+            .presign_get(&key)
+            .await
+            .map_err(|_| crate::error::Error::ServerMisconfiguration)?;
+        results.push(UploadResult { url: presigned });
+
+        recorded.insert(name, serde_json::to_value(&meta).map_err(|_| crate::error::Error::ServerMisconfiguration)?);
+    }
+
+    if !recorded.is_empty() {
+        let merge_value = serde_json::Value::Object(recorded);
+        sqlx::query!(
+            "UPDATE challenges SET attachments = COALESCE(attachments, '{}'::jsonb) || $2::jsonb WHERE public_id = $1",
+            public_id,
+            merge_value,
+        )
+        .execute(&state.db)
+        .await?;
+    }
+
+    Ok((StatusCode::OK, Json(results)))
+}
+
 pub fn router() -> Router<State> {
     Router::new()
         .route("/upload", post(upload_attachment))
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, str::FromStr};
+use std::collections::HashMap;
 
 use crate::{
     db::{update_chall_cache, DeploymentStrategy},
@@ -6,28 +6,16 @@ use crate::{
     Result, State,
 };
 use axum::{
-    extract::State as StateE,
+    extract::{Path, State as StateE},
     routing::{delete, get, patch, post},
     Json, Router,
 };
 use chrono::NaiveDateTime;
 use deployer_common::challenge::Challenge as DeployerChallenge;
-use eyre::eyre;
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgRow, FromRow, Row};
 
-impl FromStr for DeploymentStrategy {
-    type Err = eyre::Error;
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s {
-            "static" => Ok(DeploymentStrategy::Static),
-            "instanced" => Ok(DeploymentStrategy::Instanced),
-            _ => Err(eyre!("{s} is not a valid deployment strategy")),
-        }
-    }
-}
-
 #[derive(Deserialize, Serialize)]
 pub struct Challenge {
     pub id: i32,
@@ -41,6 +29,7 @@ pub struct Challenge {
     pub attachments: serde_json::Value,
     pub strategy: DeploymentStrategy,
     pub visible: bool,
+    pub watermarked: bool,
 
     pub category: Category,
     pub group: Option<ChallengeGroup>,
@@ -58,9 +47,9 @@ impl FromRow<'_, PgRow> for Challenge {
             points_max: row.try_get("points_max")?,
             flag: row.try_get("flag")?,
             attachments: row.try_get("attachments")?,
-            strategy: DeploymentStrategy::from_str(row.try_get("strategy")?)
-                .unwrap_or(DeploymentStrategy::Static),
+            strategy: row.try_get("strategy")?,
             visible: row.try_get("visible")?,
+            watermarked: row.try_get("watermarked")?,
             category: Category {
                 id: row.try_get("category_id")?,
                 name: row.try_get("category_name")?,
@@ -102,11 +91,12 @@ async fn get_challenges(StateE(state): StateE<State>, _: Admin) -> Result<Json<V
                 m.attachments,
                 m.strategy,
                 m.visible,
+                m.watermarked,
                 c.id AS category_id,
                 c.name AS category_name,
                 g.id AS group_id,
                 g.name AS group_name
-            FROM 
+            FROM
                 chall m
                 JOIN categories c ON m.category_id = c.id
                 LEFT JOIN challenge_groups g ON m.group_id = g.id",
@@ -129,6 +119,8 @@ pub struct UpsertChallenge {
     pub attachments: serde_json::Value,
     pub strategy: DeploymentStrategy,
     pub visible: bool,
+    #[serde(default)]
+    pub watermarked: bool,
 
     pub category_id: i32,
     pub group_id: Option<i32>,
@@ -154,10 +146,11 @@ async fn upsert_challenge(
                 visible,
                 category_id,
                 group_id,
-                strategy
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::deployment_strategy) 
-            ON CONFLICT(public_id) DO UPDATE 
-            SET 
+                strategy,
+                watermarked
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            ON CONFLICT(public_id) DO UPDATE
+            SET
                 name = $2,
                 author = $3,
                 description = $4,
@@ -168,10 +161,11 @@ async fn upsert_challenge(
                 visible = $9,
                 category_id = $10,
                 group_id = $11,
-                strategy = $12::deployment_strategy
+                strategy = $12,
+                watermarked = $13
                 RETURNING *
             )
-            SELECT 
+            SELECT
                 m.id,
                 m.public_id,
                 m.name,
@@ -181,13 +175,14 @@ async fn upsert_challenge(
                 m.points_max,
                 m.flag,
                 m.attachments,
-                m.strategy::text,
+                m.strategy,
                 m.visible,
+                m.watermarked,
                 c.id AS category_id,
                 c.name AS category_name,
                 g.id AS group_id,
                 g.name AS group_name
-            FROM 
+            FROM
                 merged m
                 JOIN categories c ON m.category_id = c.id
                 LEFT JOIN challenge_groups g ON m.group_id = g.id;",
@@ -203,14 +198,12 @@ async fn upsert_challenge(
     .bind(payload.visible)
     .bind(payload.category_id)
     .bind(payload.group_id)
-    .bind(match payload.strategy {
-        DeploymentStrategy::Static => "static",
-        DeploymentStrategy::Instanced => "instanced",
-    })
+    .bind(payload.strategy)
+    .bind(payload.watermarked)
     .fetch_one(&state.db)
     .await?;
 
-    update_chall_cache(&state.db, chall.id).await?;
+    let _ = update_chall_cache(&state.db, chall.id).await?;
 
     Ok(Json(chall))
 }
@@ -232,6 +225,28 @@ async fn delete_challenge(
     Ok(())
 }
 
+#[derive(Serialize)]
+struct WatermarkedFlag {
+    flag: String,
+}
+
+/// Previews the flag a given team would need to submit for a watermarked challenge - e.g. to
+/// confirm a handout or deployed instance is carrying the right per-team copy.
+async fn get_watermarked_flag(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Path((chall_id, team_id)): Path<(i32, i32)>,
+) -> Result<Json<WatermarkedFlag>> {
+    let flag = sqlx::query!("SELECT flag FROM challenges WHERE id = $1", chall_id)
+        .fetch_one(&state.db)
+        .await?
+        .flag;
+
+    let flag = crate::watermark::watermark_flag(state.config.watermark_secret.as_bytes(), chall_id, team_id, &flag);
+
+    Ok(Json(WatermarkedFlag { flag }))
+}
+
 #[derive(Deserialize)]
 struct CreateCategory {
     name: String,
@@ -261,12 +276,6 @@ async fn list_categories(StateE(state): StateE<State>, _: Admin) -> Result<Json<
     ))
 }
 
-#[derive(Serialize)]
-struct ChallengeDeploymentReq {
-    challenge_id: i32,
-    team_id: Option<i32>,
-    // I mean technically "lifetime: Option<u64>" should be here but it's compatible without
-}
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ChallengeDeployment {
     pub id: String,
@@ -294,57 +303,63 @@ pub enum HostMapping {
     Http { subdomain: String, base: String },
 }
 
-async fn deploy_static(StateE(state): StateE<State>, _: Admin) -> Result<Json<serde_json::Value>> {
+#[derive(Serialize)]
+struct EnqueuedJobs {
+    job_ids: Vec<uuid::Uuid>,
+}
+
+// Thin enqueue endpoint: the actual deployer calls happen in the job_queue worker, so a
+// deployer hiccup only fails the one job instead of aborting the whole batch.
+async fn deploy_static(
+    StateE(state): StateE<State>,
+    _: Admin,
+) -> Result<Json<EnqueuedJobs>> {
     let ids = sqlx::query!(r#"SELECT id FROM challenges WHERE strategy = 'static'"#)
         .fetch_all(&state.db)
         .await?;
 
-    let client = reqwest::Client::new();
-
-    let mut res = Vec::new();
-
+    let mut job_ids = Vec::with_capacity(ids.len());
     for id in ids {
-        let deployment: serde_json::Value = client
-            .post(&format!(
-                "{}/api/challenge/deploy",
-                state.config.deployer_base
-            ))
-            .json(&ChallengeDeploymentReq {
+        let job_id = crate::job_queue::enqueue(
+            &state.db,
+            "deploy",
+            &crate::job_queue::ChallengeJob {
                 challenge_id: id.id,
                 team_id: None,
-            })
-            .send()
-            .await?
-            .json()
-            .await?;
-
-        println!("deployed: {}", deployment);
-        res.push(deployment);
+            },
+        )
+        .await
+        .map_err(|_| crate::error::Error::ServerMisconfiguration)?;
+        job_ids.push(job_id);
     }
-    Ok(Json(serde_json::Value::Array(res)))
+
+    Ok(Json(EnqueuedJobs { job_ids }))
 }
 
-async fn destroy_static(StateE(state): StateE<State>, _: Admin) -> Result<()> {
+async fn destroy_static(
+    StateE(state): StateE<State>,
+    _: Admin,
+) -> Result<Json<EnqueuedJobs>> {
     let ids = sqlx::query!(r#"SELECT id FROM challenges WHERE strategy = 'static'"#)
         .fetch_all(&state.db)
         .await?;
 
-    let client = reqwest::Client::new();
-
+    let mut job_ids = Vec::with_capacity(ids.len());
     for id in ids {
-        client
-            .post(&format!(
-                "{}/api/challenge/destroy",
-                state.config.deployer_base
-            ))
-            .json(&ChallengeDeploymentReq {
+        let job_id = crate::job_queue::enqueue(
+            &state.db,
+            "destroy",
+            &crate::job_queue::ChallengeJob {
                 challenge_id: id.id,
                 team_id: None,
-            })
-            .send()
-            .await?;
+            },
+        )
+        .await
+        .map_err(|_| crate::error::Error::ServerMisconfiguration)?;
+        job_ids.push(job_id);
     }
-    Ok(())
+
+    Ok(Json(EnqueuedJobs { job_ids }))
 }
 
 async fn reload_deployer(StateE(state): StateE<State>, _: Admin) -> Result<()> {
@@ -380,27 +395,6 @@ async fn load_deployer(
     Ok(())
 }
 
-async fn reap(StateE(state): StateE<State>, _: Admin) -> Result<Json<String>> {
-    let containers = sqlx::query!("SELECT challenge_id, team_id FROM challenge_deployments WHERE NOW() > expired_at AND destroyed_at IS NULL").fetch_all(&state.db).await?;
-    let client = reqwest::Client::new();
-    for container in containers {
-        client
-            .post(format!(
-                "{}/api/challenge/destroy",
-                state.config.deployer_base
-            ))
-            .json(&ChallengeDeploymentReq {
-                challenge_id: container.challenge_id,
-                team_id: container.team_id,
-            })
-            .send()
-            .await?
-            .error_for_status()?;
-    }
-
-    Ok(Json("ok".to_string()))
-}
-
 #[derive(Deserialize)]
 struct UpdateCachePayload {
     id: Option<i32>,
@@ -412,13 +406,13 @@ async fn update_cache_handler(
     Json(payload): Json<UpdateCachePayload>,
 ) -> Result<Json<String>> {
     if let Some(chall_id) = payload.id {
-        update_chall_cache(&state.db, chall_id).await?;
+        let _ = update_chall_cache(&state.db, chall_id).await?;
     } else {
         let all_chall_ids: Vec<(i32,)> = sqlx::query_as("SELECT id FROM challenges")
             .fetch_all(&state.db)
             .await?;
         for (chall_id,) in all_chall_ids {
-            update_chall_cache(&state.db, chall_id).await?;
+            let _ = update_chall_cache(&state.db, chall_id).await?;
         }
     }
     Ok(Json("Cache updated".to_string()))
@@ -431,10 +425,10 @@ pub fn router() -> Router<crate::State> {
         .route("/", patch(upsert_challenge))
         .route("/category", get(list_categories))
         .route("/category", post(create_category))
+        .route("/{chall_id}/watermark/{team_id}", get(get_watermarked_flag))
         .route("/deploy_static", post(deploy_static))
         .route("/destroy_static", post(destroy_static))
         .route("/reload_deployer", post(reload_deployer))
         .route("/load_deployer", post(load_deployer))
-        .route("/reap", delete(reap))
         .route("/update_cache", post(update_cache_handler))
 }
@@ -1,41 +1,232 @@
+use std::path::Path as FsPath;
+
 use axum::{
-    Json, Router, body::{Body, BodyDataStream}, extract::{Path, State as StateE}, http::{HeaderMap, HeaderValue, StatusCode}, routing::{get, post}
+    Json, Router, body::{Body, BodyDataStream}, extract::{Path, Query, State as StateE}, http::{HeaderMap, HeaderValue, StatusCode}, response::{IntoResponse, Redirect, Response}, routing::{get, post}
 };
-use chrono::format;
+use chrono::{format, Duration};
 use lettre::message::header;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio_util::io::ReaderStream;
 
 use crate::{
+    attachments::{DownloadTarget, RangeSelection},
     extractors::Auth,
+    jwt::{decode_attachment_token, generate_attachment_token, Claims},
     Result, State,
 };
 
+/// How long a minted attachment download link stays valid for.
+const ATTACHMENT_TOKEN_TTL_MINUTES: i64 = 5;
+
+/// A weak `ETag` derived from file size and modification time - cheap to compute and good enough
+/// to detect "this attachment was re-uploaded since you last fetched it" without hashing the
+/// whole file on every request.
+fn weak_etag(size: u64, modified: std::time::SystemTime) -> String {
+    let mtime = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("W/\"{size:x}-{mtime:x}\"")
+}
+
+/// Serves a local file, honoring conditional and range requests per RFC 7232/7233:
+/// - `If-None-Match` matching the current `ETag` short-circuits to `304 Not Modified`.
+/// - `If-Range` not matching the current `ETag` causes the `Range` header to be ignored (the
+///   file changed since the client's last partial fetch, so it must re-fetch the whole thing).
+/// - A satisfiable `Range` yields `206 Partial Content`; an unsatisfiable one yields `416`.
+async fn serve_file_with_range(abs_path: &FsPath, headers: &HeaderMap) -> Result<Response> {
+    let mut file = tokio::fs::File::open(abs_path)
+        .await
+        .map_err(|_| crate::error::Error::ServerMisconfiguration)?;
+    let metadata = file
+        .metadata()
+        .await
+        .map_err(|_| crate::error::Error::ServerMisconfiguration)?;
+    let file_size = metadata.len();
+    let etag = weak_etag(file_size, metadata.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH));
+
+    let mut header_map = HeaderMap::new();
+    header_map.append("Accept-Ranges", HeaderValue::from_static("bytes"));
+    header_map.append("ETag", HeaderValue::from_str(&etag).unwrap());
+    let content_disposition_value = format!(
+        "attachment; filename=\"{}\"",
+        abs_path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    header_map.append(
+        "Content-Disposition",
+        HeaderValue::from_str(&content_disposition_value).unwrap(),
+    );
+
+    if headers.get("If-None-Match").and_then(|v| v.to_str().ok()) == Some(etag.as_str()) {
+        return Ok((StatusCode::NOT_MODIFIED, header_map).into_response());
+    }
+
+    // a Range is only honored if If-Range is absent or matches the current ETag - otherwise the
+    // file changed since the client's last partial fetch and it needs the whole thing again
+    let range_header = match headers.get("If-Range").and_then(|v| v.to_str().ok()) {
+        Some(if_range) if if_range != etag => None,
+        _ => headers.get("Range").and_then(|v| v.to_str().ok()),
+    };
+
+    match crate::attachments::AttachmentService::parse_range(range_header, file_size) {
+        RangeSelection::Full => {
+            header_map.append("Content-Length", HeaderValue::from_str(&file_size.to_string()).unwrap());
+            let stream = ReaderStream::new(file);
+            Ok((StatusCode::OK, header_map, Body::from_stream(stream)).into_response())
+        }
+        RangeSelection::Partial { start, end } => {
+            let len = end - start + 1;
+            file.seek(std::io::SeekFrom::Start(start))
+                .await
+                .map_err(|_| crate::error::Error::ServerMisconfiguration)?;
+            header_map.append(
+                "Content-Range",
+                HeaderValue::from_str(&format!("bytes {start}-{end}/{file_size}")).unwrap(),
+            );
+            header_map.append("Content-Length", HeaderValue::from_str(&len.to_string()).unwrap());
+            let stream = ReaderStream::new(file.take(len));
+            Ok((StatusCode::PARTIAL_CONTENT, header_map, Body::from_stream(stream)).into_response())
+        }
+        RangeSelection::Unsatisfiable => {
+            header_map.append(
+                "Content-Range",
+                HeaderValue::from_str(&format!("bytes */{file_size}")).unwrap(),
+            );
+            Ok((StatusCode::RANGE_NOT_SATISFIABLE, header_map, Body::empty()).into_response())
+        }
+    }
+}
+
 async fn download_attachment(
     StateE(state): StateE<State>,
     Path(path): Path<String>,
-) -> Result<(StatusCode, HeaderMap, Body)> {
+    headers: HeaderMap,
+) -> Result<Response> {
     if !state.attachment_service.is_enabled() {
         log::warn!("Blocked user attachment download because local attachment service is disabled");
         // if you're a well behaved client, you shouldn't get here
         return Err(crate::error::Error::GenericError);
     }
-    let mut header_map = HeaderMap::new();
     let rel_path = path.trim_start_matches("/download/");
-    if state.attachment_service.check_path_servable(rel_path) {
-        let abs_path = state.attachment_service.get_attachment_path(rel_path).unwrap();
-        let file = tokio::fs::File::open(&abs_path).await.map_err(|_| crate::error::Error::ServerMisconfiguration)?;
-        let stream = ReaderStream::new(file);
-        // write content disposition header
-        let content_disposition_value = format!("attachment; filename=\"{}\"", (&abs_path).file_name().unwrap_or_default().to_string_lossy());
-        header_map.append("Content-Disposition", HeaderValue::from_str(&content_disposition_value).unwrap());
-        return Ok((StatusCode::OK, header_map, Body::from_stream(stream)));
+
+    match state
+        .attachment_service
+        .resolve_download(rel_path)
+        .await
+        .map_err(|_| crate::error::Error::ServerMisconfiguration)?
+    {
+        Some(DownloadTarget::Redirect(url)) => Ok(Redirect::temporary(&url).into_response()),
+        Some(DownloadTarget::Local(abs_path)) => serve_file_with_range(&abs_path, &headers).await,
+        None => Err(crate::error::Error::GenericError), // TODO: not found error
+    }
+}
+
+#[derive(Deserialize)]
+struct AttachmentTokenRequest {
+    path: String,
+}
+
+#[derive(Serialize)]
+struct AttachmentToken {
+    token: String,
+}
+
+// Gates an otherwise-guessable static attachment path behind a short-lived, signed token, so
+// organizers can restrict challenge files to registered (and authenticated) teams.
+async fn request_attachment_token(
+    StateE(state): StateE<State>,
+    Auth(Claims { team_id, .. }): Auth,
+    Json(payload): Json<AttachmentTokenRequest>,
+) -> Result<Json<AttachmentToken>> {
+    if !state.attachment_service.is_enabled() {
+        return Err(crate::error::Error::GenericError);
+    }
+    if !state.attachment_service.check_path_servable(&payload.path) {
+        return Err(crate::error::Error::AttachmentNotFound);
+    }
+
+    let token = generate_attachment_token(
+        &state.config.jwt_keys,
+        &team_id,
+        &payload.path,
+        Duration::minutes(ATTACHMENT_TOKEN_TTL_MINUTES),
+    )?;
+
+    Ok(Json(AttachmentToken { token }))
+}
+
+async fn download_with_token(
+    StateE(state): StateE<State>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if !state.attachment_service.is_enabled() {
+        log::warn!("Blocked user attachment download because local attachment service is disabled");
+        return Err(crate::error::Error::GenericError);
+    }
+
+    let claims = decode_attachment_token(&state.config.jwt_keys, &token)?;
+
+    match state
+        .attachment_service
+        .resolve_download(&claims.path)
+        .await
+        .map_err(|_| crate::error::Error::ServerMisconfiguration)?
+    {
+        Some(DownloadTarget::Redirect(url)) => Ok(Redirect::temporary(&url).into_response()),
+        Some(DownloadTarget::Local(abs_path)) => serve_file_with_range(&abs_path, &headers).await,
+        None => Err(crate::error::Error::AttachmentNotFound),
+    }
+}
+
+#[derive(Deserialize)]
+struct S3DownloadParams {
+    #[serde(default)]
+    redirect: bool,
+}
+
+/// Competitor-facing download for S3-backed attachments: looks the filename up in the
+/// challenge's `attachments` JSON (recorded at upload time), then mints a short-lived presigned
+/// GET URL rather than proxying the bytes through this service, so large files never flow
+/// through here. `AttachmentNotFound` covers both a challenge with no such attachment and a
+/// challenge the key doesn't resolve to.
+async fn download_s3_attachment(
+    StateE(state): StateE<State>,
+    Path((public_id, filename)): Path<(String, String)>,
+    Query(params): Query<S3DownloadParams>,
+) -> Result<Response> {
+    let attachments: Option<serde_json::Value> = sqlx::query_scalar!(
+        "SELECT attachments FROM challenges WHERE public_id = $1",
+        public_id
+    )
+    .fetch_optional(&state.db)
+    .await?;
+
+    let key = attachments
+        .as_ref()
+        .and_then(|attachments| attachments.get(&filename))
+        .and_then(|meta| meta.get("key"))
+        .and_then(|key| key.as_str())
+        .ok_or(crate::error::Error::AttachmentNotFound)?;
+
+    let presigned_url = state
+        .attachment_service
+        .presign_get(key)
+        .await
+        .map_err(|_| crate::error::Error::AttachmentNotFound)?;
+
+    if params.redirect {
+        Ok(Redirect::temporary(&presigned_url).into_response())
     } else {
-        return Err(crate::error::Error::GenericError); // TODO: not found error
+        Ok(Json(serde_json::json!({ "url": presigned_url })).into_response())
     }
 }
 
 pub fn router() -> Router<State> {
     Router::new()
         .route("/download/{*path}", get(download_attachment))
-        
+        .route("/request_token", post(request_attachment_token))
+        .route("/download_token/{token}", get(download_with_token))
+        .route("/s3/{public_id}/{filename}", get(download_s3_attachment))
 }
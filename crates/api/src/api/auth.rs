@@ -227,6 +227,64 @@ async fn resend_token_handler(
     Ok(StatusCode::OK)
 }
 
+#[derive(Deserialize, Validate)]
+pub struct RequestLoginCodeRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+// Doesn't reveal whether the email matched a team, same as resend_token_handler.
+async fn request_login_code(
+    StateE(state): StateE<State>,
+    Json(payload): Json<RequestLoginCodeRequest>,
+) -> Result<StatusCode> {
+    payload.validate()?;
+
+    let team = sqlx::query_as!(
+        Team,
+        "SELECT id, public_id, name, email, division, created_at, extra_data FROM teams WHERE email = $1",
+        payload.email
+    )
+    .fetch_optional(&state.db)
+    .await?;
+
+    if let Some(team) = team {
+        state
+            .email
+            .send_login_code_email(&state.event, &team.email, &team.name, &team.public_id)
+            .await?;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize, Validate)]
+pub struct LoginCodeRequest {
+    #[validate(email)]
+    pub email: String,
+    pub code: String,
+}
+
+async fn login_with_code(
+    StateE(state): StateE<State>,
+    jar: CookieJar,
+    Json(payload): Json<LoginCodeRequest>,
+) -> Result<(CookieJar, Json<TeamId>)> {
+    payload.validate()?;
+
+    let team_public_id = state
+        .email
+        .verify_login_code(&payload.email, &payload.code)
+        .await?;
+
+    let jwt = generate_jwt(&state.config.jwt_keys, &team_public_id, Duration::days(30))?;
+
+    let mut cookie = Cookie::new("token", jwt);
+    cookie.set_path("/");
+    cookie.set_max_age(time::Duration::days(30));
+    Ok((jar.add(cookie), Json(TeamId { id: team_public_id })))
+}
+
 pub fn router() -> Router<State> {
     Router::new()
         .route("/register", post(register))
@@ -235,4 +293,6 @@ pub fn router() -> Router<State> {
         .route("/gen_token", get(gen_token))
         .route("/verification_details", post(get_verification_details))
         .route("/resend_token", post(resend_token_handler))
+        .route("/request_login_code", post(request_login_code))
+        .route("/login_code", post(login_with_code))
 }
@@ -3,12 +3,14 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 use crate::{db::update_chall_cache, extractors::Auth, Error, Result, State};
 use axum::{
     extract::{Path, State as StateE},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
     routing::{delete, get, post},
     Json, Router,
 };
-use bitstream_io::{BitRead, BitReader, LittleEndian};
 use chrono::{NaiveDateTime, Utc};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use tokio_stream::wrappers::BroadcastStream;
 use tower_governor::{
     governor::GovernorConfigBuilder, key_extractor::SmartIpKeyExtractor, GovernorLayer,
 };
@@ -117,16 +119,36 @@ pub async fn challenge_solves(
     Ok(Json(chall_solves))
 }
 
+#[derive(Serialize)]
+pub struct CooldownState {
+    retry_after: Option<i64>,
+}
+
+/// Lets the frontend disable the submit button without guessing. The cooldown itself is an
+/// in-memory map lookup; only the team and challenge ids are resolved from the DB.
+pub async fn get_cooldown(
+    StateE(state): StateE<State>,
+    Auth(claims): Auth,
+    Path(chall_id): Path<String>,
+) -> Result<Json<CooldownState>> {
+    let team_id: i32 = sqlx::query_scalar!("SELECT id FROM teams WHERE public_id = $1", claims.team_id)
+        .fetch_one(&state.db)
+        .await?;
+    let challenge_id: i32 = sqlx::query_scalar!("SELECT id FROM challenges WHERE public_id = $1", chall_id)
+        .fetch_one(&state.db)
+        .await?;
+
+    Ok(Json(CooldownState {
+        retry_after: state.submission_throttle.retry_after(team_id, challenge_id),
+    }))
+}
+
 #[derive(Deserialize)]
 pub struct Submission {
     flag: String,
     challenge_id: String,
 }
 
-fn leet<R>(flag: String, bits: BitReader<R, LittleEndian>) -> String {
-    "".to_string()
-}
-
 pub async fn submit(
     StateE(state): StateE<State>,
     Auth(claims): Auth,
@@ -144,73 +166,153 @@ pub async fn submit(
         id: i32,
         flag: String,
         solves: i32,
+        watermarked: bool,
     }
 
     let answer_info: AnswerInfo = sqlx::query_as!(
         AnswerInfo,
-        "SELECT id, flag, c_solves AS solves FROM challenges WHERE public_id = $1",
+        "SELECT id, flag, c_solves AS solves, watermarked FROM challenges WHERE public_id = $1",
         submission.challenge_id
     )
     .fetch_one(&state.db)
     .await?;
 
-    let is_correct = answer_info.flag == submission.flag;
+    struct Team {
+        id: i32,
+        name: String,
+    }
+
+    let team = sqlx::query_as!(Team, "SELECT id, name FROM teams WHERE public_id = $1", claims.team_id)
+        .fetch_one(&state.db)
+        .await?;
+
+    if !claims.ethereal() {
+        if let Some(retry_after) = state.submission_throttle.retry_after(team.id, answer_info.id) {
+            return Err(Error::SubmissionCooldown(retry_after));
+        }
+    }
+
+    // for a watermarked flag, a submission whose watermark belongs to another team is recorded
+    // as incorrect (plus a `flag_leaks` row) and answered with `WrongFlag`; a solve is only
+    // awarded once we've confirmed the watermark belongs to the submitting team
+    let mut is_correct = false;
+    let mut leaked_to = None;
+
+    if answer_info.watermarked {
+        let (valid, bits) = crate::watermark::recover(&answer_info.flag, &submission.flag);
+        if valid {
+            let own_bits = crate::watermark::expected_bits(
+                state.config.watermark_secret.as_bytes(),
+                answer_info.id,
+                team.id,
+                bits.len(),
+            );
+
+            if crate::watermark::constant_time_eq(&bits, &own_bits) {
+                is_correct = true;
+            } else {
+                let other_teams = sqlx::query_as!(Team, "SELECT id, name FROM teams WHERE id != $1", team.id)
+                    .fetch_all(&state.db)
+                    .await?;
+
+                for other in other_teams {
+                    let other_bits = crate::watermark::expected_bits(
+                        state.config.watermark_secret.as_bytes(),
+                        answer_info.id,
+                        other.id,
+                        bits.len(),
+                    );
+                    if crate::watermark::constant_time_eq(&bits, &other_bits) {
+                        leaked_to = Some(other);
+                        break;
+                    }
+                }
+            }
+        }
+    } else {
+        is_correct =
+            crate::watermark::constant_time_eq(answer_info.flag.as_bytes(), submission.flag.as_bytes());
+    }
 
     if !claims.ethereal() {
         sqlx::query!(
             r#"INSERT INTO submissions (submission, is_correct, team_id, challenge_id)
-            VALUES ($1, $2, (SELECT id FROM teams WHERE public_id = $3), $4)"#,
+            VALUES ($1, $2, $3, $4)"#,
             submission.flag,
             is_correct,
-            claims.team_id,
+            team.id,
             answer_info.id,
         )
         .execute(&state.db)
         .await?;
+
+        if let Some(ref attributed_team) = leaked_to {
+            sqlx::query!(
+                r#"INSERT INTO flag_leaks (challenge_id, submitting_team_id, attributed_team_id, submission)
+                VALUES ($1, $2, $3, $4)"#,
+                answer_info.id,
+                team.id,
+                attributed_team.id,
+                submission.flag,
+            )
+            .execute(&state.db)
+            .await?;
+        }
+
+        // a leak still gets the submission countermeasure treatment - it's not the
+        // submitting team's own flag
+        if is_correct && leaked_to.is_none() {
+            state.submission_throttle.clear(team.id, answer_info.id);
+        } else {
+            state.submission_throttle.record_wrong(team.id, answer_info.id);
+        }
+    }
+
+    if let Some(attributed_team) = leaked_to {
+        let challenge = submission.challenge_id.clone();
+        state
+            .notifier
+            .dispatch(crate::notifier::Event::FlagLeaked {
+                submitting_team: team.name,
+                attributed_team: attributed_team.name,
+                challenge,
+            })
+            .await;
+        return Err(Error::WrongFlag);
     }
 
     if is_correct {
         if !claims.ethereal() {
-            update_chall_cache(&state.db, answer_info.id).await?;
+            let new_solves = update_chall_cache(&state.db, answer_info.id).await?;
+
             if answer_info.solves == 0 {
-                if let Some(u) = state.config.bloodbot_discord_webhook_url.as_ref() {
-                    // TODO(aiden): make this hookable instead of just vomitting this code here
-                    let client = reqwest::Client::new();
-
-                    #[derive(Serialize)]
-                    struct WebhookData {
-                        content: String,
-                        embeds: Option<()>,
-                        attachments: Vec<()>,
-                    }
-                    let msg = format!(
-                        "Congrats to `{}` for first blooding `{}`!",
-                        sqlx::query!(
-                            "SELECT name FROM teams WHERE public_id = $1",
-                            claims.team_id
-                        )
-                        .fetch_one(&state.db)
-                        .await?
-                        .name,
-                        sqlx::query!(
-                            "SELECT public_id FROM challenges WHERE id = $1",
-                            answer_info.id
-                        )
-                        .fetch_one(&state.db)
-                        .await?
-                        .public_id
-                    );
-                    client
-                        .post(u)
-                        .json(&WebhookData {
-                            content: msg,
-                            embeds: None,
-                            attachments: Vec::new(),
-                        })
-                        .send()
-                        .await?;
-                }
+                state
+                    .notifier
+                    .dispatch(crate::notifier::Event::FirstBlood {
+                        team: team.name.clone(),
+                        challenge: submission.challenge_id.clone(),
+                    })
+                    .await;
+                // no subscribers is not an error - the channel just drops the event
+                let _ = state
+                    .scoreboard_events
+                    .send(crate::scoreboard::ScoreboardEvent::FirstBlood {
+                        team: team.name,
+                        challenge: submission.challenge_id.clone(),
+                    });
+            } else {
+                state
+                    .notifier
+                    .dispatch(crate::notifier::Event::Solve { team: team.name, challenge: submission.challenge_id.clone() })
+                    .await;
             }
+
+            let _ = state
+                .scoreboard_events
+                .send(crate::scoreboard::ScoreboardEvent::Solve {
+                    challenge: submission.challenge_id,
+                    solves: new_solves,
+                });
         }
         Ok(())
     } else {
@@ -296,6 +398,36 @@ WHERE teams.public_id = $1 AND challenges.public_id = $2 AND challenges.visible
         .json()
         .await?;
 
+    if deployment.deployed {
+        let connections = deployment
+            .data
+            .as_ref()
+            .map(|data| {
+                data.values()
+                    .flat_map(|d| d.ports.values())
+                    .map(|mapping| match mapping {
+                        HostMapping::Tcp { port, base } => {
+                            crate::notifications::ConnectionInfo::Tcp { host: base.clone(), port: *port }
+                        }
+                        HostMapping::Http { subdomain, base } => {
+                            crate::notifications::ConnectionInfo::Http { url: format!("https://{subdomain}.{base}") }
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        state
+            .notifications
+            .dispatch(
+                &state.db,
+                record.team_id,
+                &deployment.id,
+                crate::notifications::DeploymentEvent::Ready { deployment_id: deployment.id.clone(), connections },
+            )
+            .await;
+    }
+
     Ok(Json(deployment))
 }
 
@@ -372,6 +504,61 @@ async fn get_deployment(
     }))
 }
 
+/// Streams snapshots of a single deployment as deployer-server's async worker moves it through
+/// deploying/ready/destroyed, so the frontend doesn't have to poll `/deploy/get/{id}` while
+/// waiting for a deploy to land.
+async fn stream_deployment(
+    StateE(state): StateE<State>,
+    Auth(claims): Auth,
+    Path(pub_id): Path<String>,
+) -> Result<Sse<impl futures_util::Stream<Item = std::result::Result<SseEvent, std::convert::Infallible>>>> {
+    let team_id: i32 = sqlx::query_scalar!("SELECT id FROM teams WHERE public_id = $1", claims.team_id)
+        .fetch_one(&state.db)
+        .await?;
+
+    let record = sqlx::query!(
+        "SELECT team_id FROM challenge_deployments WHERE public_id = $1",
+        pub_id,
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| Error::NotFoundChallenge)?;
+
+    // mirrors the visibility rule in `list()`: a deployment with no owning team is shared/static
+    if let Some(owning_team) = record.team_id {
+        if owning_team != team_id {
+            return Err(Error::NotFoundChallenge);
+        }
+    }
+
+    let stream = BroadcastStream::new(state.deployment_events.subscribe()).filter_map(move |item| async move {
+        let snapshot = item.ok()?;
+        if snapshot.id != pub_id {
+            return None;
+        }
+
+        let data = serde_json::to_string(&snapshot).ok()?;
+        Some(Ok(SseEvent::default().event("deployment").data(data)))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Streams scoreboard-wide solve/first-blood deltas, so the scoreboard updates live instead of
+/// every competitor re-fetching `/challs/` on a timer.
+async fn stream_scoreboard(
+    Auth(_): Auth,
+    StateE(state): StateE<State>,
+) -> Sse<impl futures_util::Stream<Item = std::result::Result<SseEvent, std::convert::Infallible>>> {
+    let stream = BroadcastStream::new(state.scoreboard_events.subscribe()).filter_map(|item| async move {
+        let event = item.ok()?;
+        let data = serde_json::to_string(&event).ok()?;
+        Some(Ok(SseEvent::default().data(data)))
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
 // pub async fn get_deployment(
 //     Auth(_): Auth,
 //     Path(pub_id): Path<String>,
@@ -420,5 +607,8 @@ pub fn router() -> Router<crate::State> {
         .merge(ratelimited)
         .route("/", get(list))
         .route("/solves/{chall_id}", get(challenge_solves))
+        .route("/cooldown/{chall_id}", get(get_cooldown))
         .route("/deploy/get/{deployment_id}", get(get_deployment))
+        .route("/deploy/stream/{deployment_id}", get(stream_deployment))
+        .route("/stream", get(stream_scoreboard))
 }
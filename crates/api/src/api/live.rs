@@ -0,0 +1,37 @@
+use axum::{
+    extract::State as StateE,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures_util::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::{extractors::Auth, jwt::Claims, Result, State};
+
+/// Streams the authenticated team's deployment lifecycle notifications (ready/expiring/destroyed)
+/// as SSE, so the scoreboard can show live instance status instead of polling.
+async fn stream_deployment_events(
+    StateE(state): StateE<State>,
+    Auth(Claims { team_id, .. }): Auth,
+) -> Result<Sse<impl futures_util::Stream<Item = std::result::Result<Event, std::convert::Infallible>>>> {
+    let team_id: i32 = sqlx::query_scalar!("SELECT id FROM teams WHERE public_id = $1", team_id)
+        .fetch_one(&state.db)
+        .await?;
+
+    let stream = BroadcastStream::new(state.notifications.sse.subscribe()).filter_map(move |item| async move {
+        let notification = item.ok()?;
+        if notification.team_id != team_id {
+            return None;
+        }
+
+        let data = serde_json::to_string(&notification.event).ok()?;
+        Some(Ok(Event::default().event(notification.event.event_type()).data(data)))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+pub fn router() -> Router<State> {
+    Router::new().route("/stream", get(stream_deployment_events))
+}
@@ -4,6 +4,7 @@ mod attachments;
 mod auth;
 mod challenges;
 mod leaderboard;
+mod live;
 mod profile;
 
 // TODO: is this really how we want to import the team struct into admin?
@@ -16,6 +17,7 @@ pub fn router(config: &crate::config::Config) -> Router<crate::State> {
         .nest("/auth", auth::router())
         .nest("/challs", challenges::router(config))
         .nest("/event", crate::event::router())
+        .nest("/live", live::router())
         .nest("/profile", profile::router())
         .nest("/leaderboard", leaderboard::router())
 }
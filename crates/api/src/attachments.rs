@@ -1,10 +1,45 @@
-use std::path::PathBuf;
+use std::{
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use aws_sdk_s3::{
+    config::{Credentials, Region},
+    presigning::PresigningConfig,
+    types::{CompletedMultipartUpload, CompletedPart},
+    Client as S3Client,
+};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 
 use crate::config::Config;
 
+/// Result of matching a `Range` request header against a file's size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeSelection {
+    /// No `Range` header was present, or it didn't use the `bytes=` unit - serve the whole file.
+    Full,
+    /// A satisfiable single byte range, inclusive on both ends.
+    Partial { start: u64, end: u64 },
+    /// The header was present but couldn't be honored (multi-range, malformed, or out of bounds).
+    Unsatisfiable,
+}
+
+/// Rejects any path with a `..`, root, or prefix component. `Path::starts_with` (used by
+/// `check_path` below) is a purely lexical prefix-of-components check and never resolves `..`,
+/// so without this a team-supplied path like `../../../../etc/passwd` still has
+/// `attachments_path`'s components as a literal prefix once joined, even though the OS would
+/// resolve it straight out of the attachments directory.
+fn has_traversal(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+}
+
 pub struct AttachmentService {
     pub attachments_path: Option<PathBuf>,
     pub attachments_serving_url: String,
+    pub s3: Option<S3Backend>,
 }
 
 impl AttachmentService {
@@ -25,18 +60,22 @@ impl AttachmentService {
                 let _ = std::fs::create_dir_all(attachment_path);
             }
         }
-        
+
         Self {
             attachments_path: maybe_attachment_path,
             attachments_serving_url: maybe_attachments_serving_url.unwrap_or_else(|| format!("{}/api/attachments/download", config.cors_origin)),
+            s3: S3Backend::from_config(config),
         }
     }
 
     pub fn is_enabled(&self) -> bool {
-        self.attachments_path.is_some()
+        self.attachments_path.is_some() || self.s3.is_some()
     }
 
     pub fn check_path(&self, path: &str) -> bool {
+        if has_traversal(path) {
+            return false;
+        }
         if let Some(attachment_path) = &self.attachments_path {
             attachment_path.join(path).starts_with(attachment_path)
         } else {
@@ -62,4 +101,404 @@ impl AttachmentService {
             None
         }
     }
-}
\ No newline at end of file
+
+    /// Parses a `Range: bytes=...` header value against `file_size`, per RFC 7233. Only a
+    /// single range is supported - a value containing a comma (multiple ranges) is treated as
+    /// unsatisfiable rather than honored as `multipart/byteranges`. `bytes=N-` means "from N to
+    /// the end" and `bytes=-N` means "the last N bytes".
+    pub fn parse_range(range_header: Option<&str>, file_size: u64) -> RangeSelection {
+        let Some(spec) = range_header.and_then(|v| v.strip_prefix("bytes=")) else {
+            return RangeSelection::Full;
+        };
+
+        if spec.contains(',') || file_size == 0 {
+            return RangeSelection::Unsatisfiable;
+        }
+
+        let Some((start_s, end_s)) = spec.split_once('-') else {
+            return RangeSelection::Unsatisfiable;
+        };
+
+        let (start, end) = if start_s.is_empty() {
+            let Ok(suffix_len) = end_s.parse::<u64>() else {
+                return RangeSelection::Unsatisfiable;
+            };
+            if suffix_len == 0 {
+                return RangeSelection::Unsatisfiable;
+            }
+            (file_size.saturating_sub(suffix_len), file_size - 1)
+        } else {
+            let Ok(start) = start_s.parse::<u64>() else {
+                return RangeSelection::Unsatisfiable;
+            };
+            let end = if end_s.is_empty() {
+                file_size - 1
+            } else {
+                match end_s.parse::<u64>() {
+                    Ok(end) => end.min(file_size - 1),
+                    Err(_) => return RangeSelection::Unsatisfiable,
+                }
+            };
+            (start, end)
+        };
+
+        if start > end || start >= file_size {
+            return RangeSelection::Unsatisfiable;
+        }
+
+        RangeSelection::Partial { start, end }
+    }
+
+    /// Short-lived (SigV4) GET URL for an object key. Returns an error when no S3 backend is
+    /// configured.
+    pub async fn presign_get(&self, key: &str) -> eyre::Result<String> {
+        let backend = self
+            .s3
+            .as_ref()
+            .ok_or_else(|| eyre::eyre!("S3 attachment backend is not configured"))?;
+
+        backend.presign_get(key).await
+    }
+
+    /// Resolves a generic `/download/{path}`-style request to wherever the bytes actually live.
+    /// When an S3 backend is configured and an object exists under `rel_path` (treating it as a
+    /// bucket key - the same `<public_id>/<filename>` convention `S3Backend::object_key` uses),
+    /// the caller should redirect to a presigned URL instead of proxying the file itself. Falls
+    /// back to the local-disk path otherwise, so small deployments without a bucket configured
+    /// keep working unchanged.
+    pub async fn resolve_download(&self, rel_path: &str) -> eyre::Result<Option<DownloadTarget>> {
+        if let Some(backend) = &self.s3 {
+            if backend.head_object(rel_path).await.unwrap_or(false) {
+                return Ok(Some(DownloadTarget::Redirect(backend.presign_get(rel_path).await?)));
+            }
+        }
+
+        if self.check_path_servable(rel_path) {
+            return Ok(self.get_attachment_path(rel_path).map(DownloadTarget::Local));
+        }
+
+        Ok(None)
+    }
+}
+
+/// Where a resolved attachment download should actually come from.
+pub enum DownloadTarget {
+    Local(PathBuf),
+    Redirect(String),
+}
+
+/// Metadata recorded into a challenge's `attachments` JSON column for each object we put in the
+/// bucket, so the competitor-facing download handler can tell a missing key from a missing file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentObjectMeta {
+    pub key: String,
+    pub size: u64,
+    pub content_type: String,
+    pub etag: String,
+}
+
+/// Thin wrapper around an `aws-sdk-s3` client pointed at a single bucket, for S3-compatible
+/// stores (Garage, MinIO) rather than AWS itself.
+pub struct S3Backend {
+    client: S3Client,
+    bucket: String,
+    presign_ttl: Duration,
+    multipart_part_size: usize,
+}
+
+impl S3Backend {
+    fn from_config(config: &Config) -> Option<Self> {
+        let endpoint = config.s3_endpoint.clone()?;
+        let bucket = config.s3_bucket.clone()?;
+        let access_key_id = config.s3_access_key_id.clone()?;
+        let secret_access_key = config.s3_secret_access_key.clone()?;
+
+        let credentials = Credentials::new(access_key_id, secret_access_key, None, None, "nerine-config");
+        let s3_config = aws_sdk_s3::Config::builder()
+            .behavior_version_latest()
+            .endpoint_url(endpoint)
+            .region(Region::new(config.s3_region.clone()))
+            .credentials_provider(credentials)
+            .force_path_style(config.s3_force_path_style)
+            .build();
+
+        Some(Self {
+            client: S3Client::from_conf(s3_config),
+            bucket,
+            presign_ttl: Duration::from_secs(config.attachment_presign_ttl_secs),
+            multipart_part_size: config.s3_multipart_part_size_bytes,
+        })
+    }
+
+    /// Object key convention: `<public_id>/<filename>`, so a challenge's attachments sit
+    /// together in the bucket and can't collide with another challenge's files.
+    pub fn object_key(public_id: &str, filename: &str) -> String {
+        format!("{public_id}/{filename}")
+    }
+
+    pub async fn presign_get(&self, key: &str) -> eyre::Result<String> {
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(PresigningConfig::expires_in(self.presign_ttl)?)
+            .await?;
+
+        Ok(presigned.uri().to_string())
+    }
+
+    /// Streams `body` into the bucket under `key`. Uploads that stay under
+    /// `multipart_part_size` go through a single `PutObject`; larger ones start a multipart
+    /// upload once the buffered bytes cross that threshold, so we never hold a whole large
+    /// attachment in memory at once.
+    pub async fn put_object_stream<S>(
+        &self,
+        key: &str,
+        content_type: &str,
+        mut stream: S,
+    ) -> eyre::Result<AttachmentObjectMeta>
+    where
+        S: Stream<Item = std::io::Result<bytes::Bytes>> + Unpin,
+    {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut upload_id: Option<String> = None;
+        let mut completed_parts: Vec<CompletedPart> = Vec::new();
+        let mut part_number: i32 = 1;
+        let mut total_size: u64 = 0;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            total_size += chunk.len() as u64;
+            buffer.extend_from_slice(&chunk);
+
+            if buffer.len() >= self.multipart_part_size {
+                if upload_id.is_none() {
+                    let create = self
+                        .client
+                        .create_multipart_upload()
+                        .bucket(&self.bucket)
+                        .key(key)
+                        .content_type(content_type)
+                        .send()
+                        .await?;
+                    upload_id = Some(
+                        create
+                            .upload_id()
+                            .ok_or_else(|| eyre::eyre!("bucket did not return an upload id"))?
+                            .to_string(),
+                    );
+                }
+
+                let id = upload_id.as_ref().unwrap();
+                let part = self
+                    .client
+                    .upload_part()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(id)
+                    .part_number(part_number)
+                    .body(std::mem::take(&mut buffer).into())
+                    .send()
+                    .await?;
+
+                completed_parts.push(
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .e_tag(part.e_tag().unwrap_or_default())
+                        .build(),
+                );
+                part_number += 1;
+            }
+        }
+
+        let etag = if let Some(id) = upload_id {
+            if !buffer.is_empty() {
+                let part = self
+                    .client
+                    .upload_part()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(&id)
+                    .part_number(part_number)
+                    .body(buffer.into())
+                    .send()
+                    .await?;
+
+                completed_parts.push(
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .e_tag(part.e_tag().unwrap_or_default())
+                        .build(),
+                );
+            }
+
+            let completed = self
+                .client
+                .complete_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(&id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(completed_parts))
+                        .build(),
+                )
+                .send()
+                .await?;
+
+            completed.e_tag().unwrap_or_default().trim_matches('"').to_string()
+        } else {
+            let output = self
+                .client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .content_type(content_type)
+                .body(buffer.into())
+                .send()
+                .await?;
+
+            output.e_tag().unwrap_or_default().trim_matches('"').to_string()
+        };
+
+        Ok(AttachmentObjectMeta {
+            key: key.to_string(),
+            size: total_size,
+            content_type: content_type.to_string(),
+            etag,
+        })
+    }
+
+    pub async fn head_object(&self, key: &str) -> eyre::Result<bool> {
+        match self.client.head_object().bucket(&self.bucket).key(key).send().await {
+            Ok(_) => Ok(true),
+            Err(aws_sdk_s3::error::SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod path_traversal_tests {
+    use super::{has_traversal, AttachmentService};
+
+    #[test]
+    fn accepts_plain_relative_paths() {
+        assert!(!has_traversal("public_id/file.zip"));
+        assert!(!has_traversal("file.zip"));
+    }
+
+    #[test]
+    fn rejects_parent_dir_components() {
+        assert!(has_traversal("../../../../etc/passwd"));
+        assert!(has_traversal("public_id/../../etc/passwd"));
+        assert!(has_traversal(".."));
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        assert!(has_traversal("/etc/passwd"));
+    }
+
+    #[test]
+    fn check_path_rejects_traversal_even_though_it_is_a_lexical_prefix_match() {
+        let service = AttachmentService {
+            attachments_path: Some(std::path::PathBuf::from("/data/attachments")),
+            attachments_serving_url: String::new(),
+            s3: None,
+        };
+
+        assert!(service.check_path("public_id/file.zip"));
+        // a lexical `starts_with` on the joined path would wrongly accept this - it's only
+        // rejected because `has_traversal` catches it before the join happens
+        assert!(!service.check_path("../../../../etc/passwd"));
+    }
+}
+
+#[cfg(test)]
+mod parse_range_tests {
+    use super::{AttachmentService, RangeSelection};
+
+    #[test]
+    fn no_header_serves_the_whole_file() {
+        assert_eq!(AttachmentService::parse_range(None, 100), RangeSelection::Full);
+    }
+
+    #[test]
+    fn plain_range_is_honored() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=0-49"), 100),
+            RangeSelection::Partial { start: 0, end: 49 }
+        );
+    }
+
+    #[test]
+    fn open_ended_range_goes_to_the_end() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=50-"), 100),
+            RangeSelection::Partial { start: 50, end: 99 }
+        );
+    }
+
+    #[test]
+    fn suffix_range_serves_the_last_n_bytes() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=-10"), 100),
+            RangeSelection::Partial { start: 90, end: 99 }
+        );
+    }
+
+    #[test]
+    fn suffix_range_longer_than_the_file_clamps_to_the_start() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=-1000"), 100),
+            RangeSelection::Partial { start: 0, end: 99 }
+        );
+    }
+
+    #[test]
+    fn zero_length_suffix_is_unsatisfiable() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=-0"), 100),
+            RangeSelection::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn end_past_file_size_clamps_to_the_last_byte() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=0-999"), 100),
+            RangeSelection::Partial { start: 0, end: 99 }
+        );
+    }
+
+    #[test]
+    fn multi_range_is_unsatisfiable() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=0-10,20-30"), 100),
+            RangeSelection::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn start_beyond_file_size_is_unsatisfiable() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=100-200"), 100),
+            RangeSelection::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn malformed_spec_is_unsatisfiable() {
+        assert_eq!(
+            AttachmentService::parse_range(Some("bytes=abc-def"), 100),
+            RangeSelection::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn empty_file_is_always_unsatisfiable() {
+        assert_eq!(AttachmentService::parse_range(Some("bytes=0-0"), 0), RangeSelection::Unsatisfiable);
+    }
+}
@@ -0,0 +1,26 @@
+//! Standalone ops tool: applies any pending `migrations/` without booting the rest of the
+//! server, for environments that run with `AUTO_APPLY_MIGRATIONS=false`.
+
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgPoolOptions;
+
+static MIGRATOR: Migrator = sqlx::migrate!("../../migrations");
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    pretty_env_logger::init();
+    dotenvy::dotenv().ok();
+
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&database_url)
+        .await?;
+
+    MIGRATOR.run(&pool).await?;
+
+    log::info!("database schema is up to date");
+
+    Ok(())
+}
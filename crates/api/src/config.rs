@@ -3,7 +3,7 @@ use std::{str::FromStr, sync::Arc};
 use envconfig::Envconfig;
 use jsonwebtoken::{DecodingKey, EncodingKey};
 
-use crate::{DB, attachments, email, event::Event};
+use crate::{DB, attachments, deployment_watch, email, event::Event, notifications, notifier, scoreboard, submission_throttle};
 
 pub struct JwtKeys {
     pub encoding: EncodingKey,
@@ -32,6 +32,11 @@ pub struct Config {
     #[envconfig(from = "ADMIN_TOKEN")]
     pub admin_token: String,
 
+    // HMAC key for per-team flag watermarking - keep this as secret as JWT_SECRET/ADMIN_TOKEN,
+    // since anyone who has it can forge a flag watermarked as any team
+    #[envconfig(from = "WATERMARK_SECRET")]
+    pub watermark_secret: String,
+
     #[envconfig(from = "EVENT_PATH", default = "event.toml")]
     pub event_path: String,
 
@@ -50,6 +55,16 @@ pub struct Config {
     #[envconfig(from = "BLOODBOT_DISCORD_WEBHOOK_URL")]
     pub bloodbot_discord_webhook_url: Option<String>,
 
+    // generic JSON webhook sink for the notifier subsystem (first bloods, solves, event
+    // start/end, ...) - independent of the Discord sink above and of deployment notifications
+    #[envconfig(from = "NOTIFIER_WEBHOOK_URL")]
+    pub notifier_webhook_url: Option<String>,
+
+    // body template for the sink above; `{{message}}` is substituted in. Defaults to
+    // `{"message": "..."}` if a URL is set without a template.
+    #[envconfig(from = "NOTIFIER_WEBHOOK_BODY_TEMPLATE")]
+    pub notifier_webhook_body_template: Option<String>,
+
     #[envconfig(from = "INSTANCE_LIFETIME", default = "600")]
     pub instance_lifetime: u64,
 
@@ -65,6 +80,105 @@ pub struct Config {
 
     #[envconfig(from = "EMAIL_DOMAIN_WHITELIST")]
     pub email_domain_whitelist: Option<String>,
+
+    #[envconfig(from = "SMTP_SECURITY", default = "starttls")]
+    pub smtp_security: SmtpSecurity,
+
+    // e.g. "tls1.2", "tls1.3" - rejected below that version during the TLS handshake
+    #[envconfig(from = "SMTP_MIN_TLS_VERSION")]
+    pub smtp_min_tls_version: Option<String>,
+
+    // extra PEM root certificate(s) to trust, for relays behind a private CA or self-signed cert
+    #[envconfig(from = "SMTP_ROOT_CERT")]
+    pub smtp_root_cert: Option<String>,
+
+    #[envconfig(from = "SMTP_ACCEPT_INVALID_CERTS", default = "false")]
+    pub smtp_accept_invalid_certs: bool,
+
+    // directory of `<name>.txt.tera`/`<name>.html.tera` overrides for the built-in email
+    // templates, so an event can re-brand emails without touching the binary
+    #[envconfig(from = "TEMPLATE_DIR")]
+    pub template_dir: Option<String>,
+
+    // S3-compatible bucket (Garage, MinIO, ...) for challenge attachments. When set, admin
+    // uploads go to the bucket instead of local disk; downloads look in the bucket first and
+    // fall back to the local-disk directory.
+    #[envconfig(from = "S3_ENDPOINT")]
+    pub s3_endpoint: Option<String>,
+
+    #[envconfig(from = "S3_REGION", default = "auto")]
+    pub s3_region: String,
+
+    #[envconfig(from = "S3_BUCKET")]
+    pub s3_bucket: Option<String>,
+
+    #[envconfig(from = "S3_ACCESS_KEY_ID")]
+    pub s3_access_key_id: Option<String>,
+
+    #[envconfig(from = "S3_SECRET_ACCESS_KEY")]
+    pub s3_secret_access_key: Option<String>,
+
+    // Garage/MinIO need path-style addressing (`endpoint/bucket/key`) rather than the
+    // virtual-hosted style AWS itself defaults to.
+    #[envconfig(from = "S3_FORCE_PATH_STYLE", default = "true")]
+    pub s3_force_path_style: bool,
+
+    // TTL for presigned attachment GET URLs handed to competitors.
+    #[envconfig(from = "ATTACHMENT_PRESIGN_TTL_SECS", default = "300")]
+    pub attachment_presign_ttl_secs: u64,
+
+    // uploads below this size go through a single PutObject call; larger ones are streamed
+    // in as a multipart upload so we never have to buffer the whole file for the final part.
+    #[envconfig(from = "S3_MULTIPART_PART_SIZE_BYTES", default = "8388608")]
+    pub s3_multipart_part_size_bytes: usize,
+
+    // deployment lifecycle notifications - web push (VAPID), a Discord/Slack-style webhook, and
+    // an in-process SSE channel are all optional and independently enabled by their config below
+    #[envconfig(from = "VAPID_PRIVATE_KEY_PEM")]
+    pub vapid_private_key_pem: Option<String>,
+
+    #[envconfig(from = "VAPID_SUBJECT", default = "mailto:admin@nerine.localhost")]
+    pub vapid_subject: String,
+
+    #[envconfig(from = "DEPLOYMENT_NOTIFICATION_WEBHOOK_URL")]
+    pub deployment_notification_webhook_url: Option<String>,
+
+    // how long before `expired_at` to send the "your instance is about to expire" notification
+    #[envconfig(from = "DEPLOYMENT_EXPIRY_WARNING_SECS", default = "300")]
+    pub deployment_expiry_warning_secs: i64,
+
+    // when true (the default), startup applies any pending `migrations/` to the database itself;
+    // when false, a pending migration is a hard startup error instead of silent schema drift, and
+    // the operator is expected to run the `migrate` binary first.
+    #[envconfig(from = "AUTO_APPLY_MIGRATIONS", default = "true")]
+    pub auto_apply_migrations: bool,
+}
+
+/// How the mailer should negotiate TLS with the SMTP relay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Connect in plaintext, then upgrade via `STARTTLS` (current/default behavior).
+    Starttls,
+    /// TLS from the first byte of the connection (commonly port 465).
+    Implicit,
+    /// Upgrade via `STARTTLS` if the server advertises it, otherwise stay in plaintext.
+    Opportunistic,
+    /// Never use TLS. Only really makes sense talking to a local relay in dev.
+    None,
+}
+
+impl FromStr for SmtpSecurity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "starttls" => Ok(Self::Starttls),
+            "implicit" | "wrapper" => Ok(Self::Implicit),
+            "opportunistic" => Ok(Self::Opportunistic),
+            "none" => Ok(Self::None),
+            other => Err(format!("unknown SMTP_SECURITY value {:?}", other)),
+        }
+    }
 }
 
 pub struct StateInner {
@@ -73,6 +187,15 @@ pub struct StateInner {
     pub db: DB,
     pub email: email::EmailService,
     pub attachment_service: attachments::AttachmentService,
+    pub notifications: notifications::NotificationService,
+    pub notifier: notifier::NotifierService,
+    // deployer-server owns the actual `challenge_deployments` writes; this is fed by
+    // `deployment_watch::poll_loop` rather than directly from this process's own handlers
+    pub deployment_events: tokio::sync::broadcast::Sender<deployment_watch::DeploymentSnapshot>,
+    // fed directly from `submit()` (using the solve count `update_chall_cache` returns), since
+    // that runs in this process
+    pub scoreboard_events: tokio::sync::broadcast::Sender<scoreboard::ScoreboardEvent>,
+    pub submission_throttle: submission_throttle::SubmissionThrottle,
 }
 
 impl AsRef<Config> for StateInner {
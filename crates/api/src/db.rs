@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+pub type DB = sqlx::PgPool;
+
+/// Mirrors the Postgres `deployment_strategy` enum directly via `sqlx::Type`, so an unrecognized
+/// value on the wire is a decode error instead of silently becoming `Static`, and encoding a
+/// value for a query no longer needs a hand-written `match` to a string literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "deployment_strategy", rename_all = "lowercase")]
+pub enum DeploymentStrategy {
+    Static,
+    Instanced,
+}
+
+/// Only used for parsing a strategy out of config/CLI input (e.g. event definitions), not for
+/// decoding database rows - those go through the `sqlx::Type` impl above.
+impl FromStr for DeploymentStrategy {
+    type Err = eyre::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "static" => Ok(DeploymentStrategy::Static),
+            "instanced" => Ok(DeploymentStrategy::Instanced),
+            _ => Err(eyre::eyre!("{s} is not a valid deployment strategy")),
+        }
+    }
+}
+
+/// Dynamic scoring decay window: how many correct solves after the first it takes to walk a
+/// challenge's value down from `points_max` to `points_min`.
+const DYNAMIC_SCORING_DECAY_SOLVES: i32 = 10;
+
+/// Recomputes the cached `c_points`/`c_solves` columns for a single challenge after a solve, so
+/// the scoreboard-facing reads (`api::challenges::list`, `submit`) never have to aggregate
+/// `submissions` on every request. Returns the new solve count, so callers that need to
+/// broadcast a scoreboard delta (`submit`) don't have to re-query it themselves.
+pub async fn update_chall_cache(db: &DB, challenge_id: i32) -> crate::Result<i32> {
+    let row = sqlx::query!(
+        r#"SELECT points_min, points_max,
+            (SELECT COUNT(*) FROM submissions WHERE challenge_id = $1 AND is_correct = true)::int AS "solves!"
+        FROM challenges WHERE id = $1"#,
+        challenge_id,
+    )
+    .fetch_one(db)
+    .await?;
+
+    let points = if row.solves <= 1 {
+        row.points_max
+    } else {
+        let decayed = row.points_max
+            - (row.points_max - row.points_min) * (row.solves - 1) / DYNAMIC_SCORING_DECAY_SOLVES;
+        decayed.max(row.points_min)
+    };
+
+    sqlx::query!(
+        "UPDATE challenges SET c_points = $2, c_solves = $3 WHERE id = $1",
+        challenge_id,
+        points,
+        row.solves,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(row.solves)
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use log::error;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::State;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const CHANNEL_CAPACITY: usize = 256;
+
+/// A `challenge_deployments` row snapshot, broadcast whenever deployer-server changes it
+/// (deployed/destroyed/expired). deployer-server is a separate process with its own DB
+/// connection, so this is fed by polling rather than a direct call.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploymentSnapshot {
+    pub id: String,
+    pub team_id: Option<i32>,
+    pub deployed: bool,
+    pub data: Option<serde_json::Value>,
+    pub created_at: NaiveDateTime,
+    pub expired_at: Option<NaiveDateTime>,
+    pub destroyed_at: Option<NaiveDateTime>,
+}
+
+pub fn channel() -> broadcast::Sender<DeploymentSnapshot> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// Background loop: polls for rows touched since the last pass and rebroadcasts them as
+/// snapshots, so `api::challenges::stream_deployment` doesn't have to poll the DB itself per
+/// subscriber.
+pub async fn poll_loop(state: State) {
+    let mut since = chrono::Utc::now().naive_utc();
+
+    loop {
+        match poll_once(&state, since).await {
+            Ok(Some(newest)) => since = newest,
+            Ok(None) => {}
+            Err(e) => error!("deployment_watch: poll failed: {:?}", e),
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn poll_once(state: &State, since: NaiveDateTime) -> eyre::Result<Option<NaiveDateTime>> {
+    struct Row {
+        id: String,
+        team_id: Option<i32>,
+        deployed: bool,
+        data: Option<serde_json::Value>,
+        created_at: NaiveDateTime,
+        expired_at: Option<NaiveDateTime>,
+        destroyed_at: Option<NaiveDateTime>,
+        updated_at: NaiveDateTime,
+    }
+
+    let rows = sqlx::query_as!(
+        Row,
+        r#"SELECT public_id AS id, team_id, deployed, data, created_at, expired_at, destroyed_at, updated_at
+           FROM challenge_deployments WHERE updated_at > $1 ORDER BY updated_at"#,
+        since,
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut newest = None;
+    for row in rows {
+        newest = Some(row.updated_at);
+        // no subscribers is not an error - the channel just drops the snapshot
+        let _ = state.deployment_events.send(DeploymentSnapshot {
+            id: row.id,
+            team_id: row.team_id,
+            deployed: row.deployed,
+            data: row.data,
+            created_at: row.created_at,
+            expired_at: row.expired_at,
+            destroyed_at: row.destroyed_at,
+        });
+    }
+
+    Ok(newest)
+}
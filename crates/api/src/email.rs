@@ -1,13 +1,31 @@
-use crate::{config::Config, event::Event, Result};
+use crate::{
+    config::{Config, SmtpSecurity},
+    event::Event,
+    templates::Templates,
+    Result, DB,
+};
 use cached::{Cached, TimedSizedCache};
 use lettre::{
-    message::{header::ContentType, Message},
-    transport::smtp::{authentication::Credentials, client::Tls},
-    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Certificate, Tls, TlsParameters, TlsVersion},
+    },
+    AsyncSmtpTransport, Tokio1Executor,
 };
 use nanoid::nanoid;
 use std::sync::Mutex;
 
+/// How long the verification/email-change links embedded in outgoing emails stay valid for,
+/// as surfaced to the recipient - matches the TTL on `verification_tokens`.
+const VERIFICATION_EXPIRY_MINUTES: u32 = 10;
+
+/// Login codes are numeric (easy to type from a phone) and short-lived - matches the TTL on
+/// `login_codes`.
+const LOGIN_CODE_LEN: usize = 6;
+const LOGIN_CODE_DIGITS: [char; 10] = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
+const LOGIN_CODE_TTL_SECS: u64 = 300;
+const LOGIN_CODE_MAX_ATTEMPTS: u32 = 5;
+
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct PendingTeamVerification {
     pub name: String,
@@ -26,20 +44,31 @@ pub enum PendingVerification {
     EmailUpdate(PendingEmailUpdate),
 }
 
+#[derive(Clone)]
+struct PendingLoginCode {
+    code: String,
+    team_public_id: String,
+    attempts: u32,
+}
+
 pub struct EmailService {
-    mailer: Option<AsyncSmtpTransport<Tokio1Executor>>,
-    from_email: String,
+    // pub(crate) so the mailqueue worker can drive deliveries with the same mailer/from address
+    pub(crate) mailer: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    pub(crate) from_email: String,
+    db: DB,
     app_base_url: String,
+    templates: Templates,
     verification_tokens: Mutex<TimedSizedCache<String, PendingVerification>>,
+    login_codes: Mutex<TimedSizedCache<String, PendingLoginCode>>,
     email_domain_whitelist: Vec<String>,
 }
 
 impl EmailService {
-    pub fn new(config: &Config) -> Self {
+    pub fn new(config: &Config, db: DB) -> Result<Self> {
         let mailer = if config.smtp_url.is_empty() {
             None
         } else {
-            match Self::create_mailer(&config.smtp_url) {
+            match Self::create_mailer(config) {
                 Ok(mailer) => Some(mailer),
                 Err(e) => {
                     log::error!("Failed to create mailer: {}", e);
@@ -48,11 +77,17 @@ impl EmailService {
             }
         };
 
-        Self {
+        Ok(Self {
             mailer,
             from_email: config.from_email.clone(),
+            db,
             app_base_url: config.cors_origin.clone(), // :nauseated_face:
+            templates: Templates::load(config.template_dir.as_deref())?,
             verification_tokens: Mutex::new(TimedSizedCache::with_size_and_lifespan(1000, 600)),
+            login_codes: Mutex::new(TimedSizedCache::with_size_and_lifespan(
+                1000,
+                LOGIN_CODE_TTL_SECS,
+            )),
             email_domain_whitelist: match config.email_domain_whitelist.clone() {
                 Some(whitelist_str) => {
                     whitelist_str.split(',').map(|s| s.to_string()).collect()
@@ -61,18 +96,29 @@ impl EmailService {
                     vec![]
                 },
             }
-        }
+        })
     }
 
-    fn create_mailer(smtp_url: &str) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
-        let url = url::Url::parse(smtp_url).map_err(|_| Self::validation_error())?;
+    fn create_mailer(config: &Config) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let url = url::Url::parse(&config.smtp_url).map_err(|_| Self::validation_error())?;
 
         let host = url.host_str().unwrap_or("localhost");
-        let port = url.port().unwrap_or(587);
+        let default_port = match config.smtp_security {
+            SmtpSecurity::Implicit => 465,
+            _ => 587,
+        };
+        let port = url.port().unwrap_or(default_port);
 
-        let mut mailer = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
-            .map_err(|_| Self::validation_error())?
-            .port(port);
+        let tls = match config.smtp_security {
+            SmtpSecurity::None => Tls::None,
+            SmtpSecurity::Starttls => Tls::Required(Self::tls_parameters(config, host)?),
+            SmtpSecurity::Implicit => Tls::Wrapper(Self::tls_parameters(config, host)?),
+            SmtpSecurity::Opportunistic => Tls::Opportunistic(Self::tls_parameters(config, host)?),
+        };
+
+        let mut mailer = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(port)
+            .tls(tls);
 
         if !url.username().is_empty() {
             if let Some(password) = url.password() {
@@ -91,6 +137,39 @@ impl EmailService {
         Ok(mailer.build())
     }
 
+    /// Builds the `TlsParameters` shared by every non-`none` security mode: pins a minimum TLS
+    /// version if configured, trusts an extra PEM root certificate if one was supplied, and
+    /// optionally disables certificate validation entirely for relays behind a self-signed cert.
+    ///
+    /// Note: lettre's `TlsParametersBuilder` has no knob to drop the system root store, so
+    /// `smtp_root_cert` only *adds* a trusted root rather than replacing the defaults.
+    fn tls_parameters(config: &Config, host: &str) -> Result<TlsParameters> {
+        let mut builder = TlsParameters::builder(host.to_owned());
+
+        if let Some(min_version) = &config.smtp_min_tls_version {
+            let version = match min_version.to_ascii_lowercase().replace(['.', 'v'], "").as_str() {
+                "tls10" | "10" => TlsVersion::Tlsv10,
+                "tls11" | "11" => TlsVersion::Tlsv11,
+                "tls12" | "12" => TlsVersion::Tlsv12,
+                "tls13" | "13" => TlsVersion::Tlsv13,
+                _ => return Err(Self::validation_error()),
+            };
+            builder = builder.min_tls_version(version);
+        }
+
+        if let Some(cert_path) = &config.smtp_root_cert {
+            let pem = std::fs::read(cert_path).map_err(|_| Self::validation_error())?;
+            let cert = Certificate::from_pem(&pem).map_err(|_| Self::validation_error())?;
+            builder = builder.add_root_certificate(cert);
+        }
+
+        if config.smtp_accept_invalid_certs {
+            builder = builder.dangerous_accept_invalid_certs(true);
+        }
+
+        builder.build().map_err(|_| Self::validation_error())
+    }
+
     pub async fn send_verification_email(
         &self,
         event: &Event,
@@ -112,14 +191,16 @@ impl EmailService {
             format!("{}/verify?token={}", self.app_base_url, verification_token);
 
         let subject = format!("Verify your email for {}", event.name);
-        let body = format!(
-            "Hello {},\n\nPlease click the link below to finish registering for {}:\n{}\n\nThis link will expire in approximately 10 minutes.\n\nIf you did not request this, please ignore this email.",
-            team_name_display,
-            event.name,
-            verification_link
-        );
-
-        self.send_email(to_email_addr, &subject, &body).await
+
+        let mut ctx = tera::Context::new();
+        ctx.insert("event_name", &event.name);
+        ctx.insert("team_name", team_name_display);
+        ctx.insert("verification_link", &verification_link);
+        ctx.insert("expiry_minutes", &VERIFICATION_EXPIRY_MINUTES);
+        let (body_text, body_html) = self.templates.render("verify_team", &ctx)?;
+
+        self.send_email(to_email_addr, &subject, &body_text, &body_html)
+            .await
     }
 
     pub async fn consume_pending_verification(
@@ -165,14 +246,16 @@ impl EmailService {
             format!("{}/verify?token={}", self.app_base_url, verification_token);
 
         let subject = format!("Verify your new email for {}", event.name);
-        let body = format!(
-            "Hello {},\n\nPlease click the link below to verify your new email address for {}:\n{}\n\nThis link will expire in approximately 10 minutes.\n\nIf you did not request this, please ignore this email.",
-            _new_name,
-            event.name,
-            verification_link
-        );
-
-        self.send_email(to_new_email_addr, &subject, &body).await
+
+        let mut ctx = tera::Context::new();
+        ctx.insert("event_name", &event.name);
+        ctx.insert("team_name", _new_name);
+        ctx.insert("verification_link", &verification_link);
+        ctx.insert("expiry_minutes", &VERIFICATION_EXPIRY_MINUTES);
+        let (body_text, body_html) = self.templates.render("verify_email_change", &ctx)?;
+
+        self.send_email(to_new_email_addr, &subject, &body_text, &body_html)
+            .await
     }
 
     pub async fn consume_pending_email_update(&self, token: &str) -> Result<PendingEmailUpdate> {
@@ -192,18 +275,90 @@ impl EmailService {
         token: &str,
     ) -> Result<()> {
         let subject = format!("Your team token for {}", event.name);
-        let body = format!(
-            "Hello {},\n\nHere is your team token for logging into {}:\n{}\n\nPlease keep it safe and do not share it with anyone outside your team.\n\nIf you did not request this, please ignore this email.",
-            team_name_display,
-            event.name,
-            token,
-        );
-
-        self.send_email(to_email, &subject, &body).await
+
+        let mut ctx = tera::Context::new();
+        ctx.insert("event_name", &event.name);
+        ctx.insert("team_name", team_name_display);
+        ctx.insert("token", token);
+        let (body_text, body_html) = self.templates.render("resend_token", &ctx)?;
+
+        self.send_email(to_email, &subject, &body_text, &body_html).await
     }
 
-    async fn send_email(&self, to_email: &str, subject: &str, body: &str) -> Result<()> {
-        
+    /// Issues a short numeric login code as a phishing-resistant alternative to emailing the
+    /// bearer JWT directly: the code alone can't be replayed as a session, only exchanged via
+    /// `verify_login_code`. Requesting a new code always overwrites any pending one for the
+    /// same email, so a fresh request is also how a locked-out code gets reset.
+    pub async fn send_login_code_email(
+        &self,
+        event: &Event,
+        team_email: &str,
+        team_name_display: &str,
+        team_public_id: &str,
+    ) -> Result<()> {
+        let code = nanoid!(LOGIN_CODE_LEN, &LOGIN_CODE_DIGITS);
+
+        {
+            let mut codes_cache = self.login_codes.lock().unwrap();
+            codes_cache.cache_set(
+                team_email.to_ascii_lowercase(),
+                PendingLoginCode {
+                    code: code.clone(),
+                    team_public_id: team_public_id.to_string(),
+                    attempts: 0,
+                },
+            );
+        }
+
+        let subject = format!("Your login code for {}", event.name);
+
+        let mut ctx = tera::Context::new();
+        ctx.insert("event_name", &event.name);
+        ctx.insert("team_name", team_name_display);
+        ctx.insert("code", &code);
+        ctx.insert("expiry_minutes", &(LOGIN_CODE_TTL_SECS / 60));
+        let (body_text, body_html) = self.templates.render("login_code", &ctx)?;
+
+        self.send_email(team_email, &subject, &body_text, &body_html)
+            .await
+    }
+
+    /// Checks `code` against the pending login code for `team_email`, returning the team's
+    /// public id on success. Wrong guesses are counted and the code is invalidated after
+    /// `LOGIN_CODE_MAX_ATTEMPTS` - the team has to request a fresh one at that point.
+    pub async fn verify_login_code(&self, team_email: &str, code: &str) -> Result<String> {
+        let key = team_email.to_ascii_lowercase();
+        let mut codes_cache = self.login_codes.lock().unwrap();
+
+        let entry = match codes_cache.cache_get_mut(&key) {
+            Some(entry) => entry,
+            None => return Err(crate::error::Error::InvalidToken),
+        };
+
+        if crate::watermark::constant_time_eq(entry.code.as_bytes(), code.as_bytes()) {
+            let team_public_id = entry.team_public_id.clone();
+            codes_cache.cache_remove(&key);
+            return Ok(team_public_id);
+        }
+
+        entry.attempts += 1;
+        if entry.attempts >= LOGIN_CODE_MAX_ATTEMPTS {
+            codes_cache.cache_remove(&key);
+        }
+
+        Err(crate::error::Error::InvalidToken)
+    }
+
+    /// Enqueues the email onto `pending_emails` instead of sending it inline, so a transient
+    /// relay outage doesn't fail the request - the `mailqueue` background worker delivers it
+    /// with retries. When no SMTP is configured, falls back to logging it like before.
+    async fn send_email(
+        &self,
+        to_email: &str,
+        subject: &str,
+        body_text: &str,
+        body_html: &str,
+    ) -> Result<()> {
         if !self.email_domain_whitelist.is_empty() {
             if let Some(email_domain) = to_email.split('@').last() {
                 if !self.email_domain_whitelist.contains(&email_domain.to_string()) {
@@ -213,24 +368,11 @@ impl EmailService {
                 return Err(crate::error::Error::EmailNotAllowed);
             }
         }
-        
-        if let Some(ref mailer) = self.mailer {
-            let email = Message::builder()
-                .from(
-                    self.from_email
-                        .parse()
-                        .map_err(|_| Self::validation_error())?,
-                )
-                .to(to_email.parse().map_err(|_| Self::validation_error())?)
-                .subject(subject)
-                .header(ContentType::TEXT_PLAIN)
-                .body(body.to_string())
-                .map_err(|_| Self::validation_error())?;
-
-            mailer.send(email).await.map(|_| ()).map_err(|e| {
-                log::error!("Failed to send email to {}: {}", to_email, e);
-                Self::validation_error()
-            })
+
+        if self.mailer.is_some() {
+            crate::mailqueue::enqueue(&self.db, to_email, subject, body_text, body_html)
+                .await
+                .map_err(|_| Self::validation_error())
         } else {
             log::info!(
                 "=== EMAIL (No SMTP configured) ===\n\
@@ -241,7 +383,7 @@ impl EmailService {
                 ===================================",
                 to_email,
                 subject,
-                body
+                body_text
             );
             Ok(())
         }
@@ -43,6 +43,10 @@ pub enum Error {
     AttachmentNotFound,
     #[error("Email not allowed")]
     EmailNotAllowed,
+    #[error("Failed to render email template: {0}")]
+    Template(#[from] tera::Error),
+    #[error("Too many incorrect submissions, try again in {0} seconds")]
+    SubmissionCooldown(i64),
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -60,6 +64,13 @@ pub struct EventNotStartedResponse<'a> {
     data: NaiveDateTime,
 }
 
+#[derive(Serialize)]
+pub struct SubmissionCooldownResponse<'a> {
+    error: &'a str,
+    message: String,
+    retry_after: i64,
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let message = self.to_string();
@@ -91,6 +102,18 @@ impl IntoResponse for Error {
             Error::ServerMisconfiguration => (StatusCode::INTERNAL_SERVER_ERROR, "server_misconfiguration"),
             Error::AttachmentNotFound => (StatusCode::NOT_FOUND, "attachment_not_found"),
             Error::EmailNotAllowed => (StatusCode::FORBIDDEN, "email_not_allowed"),
+            Error::Template(_) => (StatusCode::INTERNAL_SERVER_ERROR, "template_error"),
+            Error::SubmissionCooldown(retry_after) => {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(SubmissionCooldownResponse {
+                        error: "submission_cooldown",
+                        message,
+                        retry_after,
+                    }),
+                )
+                    .into_response();
+            }
         };
 
         (status, Json(ErrorResponse { error, message })).into_response()
@@ -0,0 +1,351 @@
+use std::time::Duration;
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{config::State, DB};
+
+/// How long a `running` row can go without a heartbeat before we assume its worker died and
+/// the job is reclaimable.
+const STALE_HEARTBEAT_SECS: i64 = 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const REAP_POLL_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_ATTEMPTS: i32 = 8;
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+// the deployer's `/api/challenge/destroy` endpoint only enqueues its own `deployment_jobs` row
+// and returns right away - we poll for `destroyed_at` to actually land before treating the job
+// as done, instead of declaring victory as soon as it's enqueued
+const DESTROY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const DESTROY_POLL_MAX_ATTEMPTS: u32 = 150; // ~5 minutes
+
+/// Payload shared by the `deploy`/`destroy` queues - the deployer only needs to know which
+/// challenge (and, for instanced deployments, which team) to act on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChallengeJob {
+    pub challenge_id: i32,
+    pub team_id: Option<i32>,
+}
+
+/// Enqueues a job and returns its id so the caller (an admin HTTP handler) can hand it back for
+/// polling instead of blocking on the deployer call itself.
+pub async fn enqueue(db: &DB, queue: &str, job: &ChallengeJob) -> eyre::Result<Uuid> {
+    let job_value = serde_json::to_value(job)?;
+
+    let row = sqlx::query!(
+        "INSERT INTO job_queue (queue, job) VALUES ($1, $2) RETURNING id",
+        queue,
+        job_value,
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(row.id)
+}
+
+struct ClaimedJob {
+    id: Uuid,
+    queue: String,
+    job: serde_json::Value,
+    attempts: i32,
+}
+
+async fn claim_one(db: &DB) -> eyre::Result<Option<ClaimedJob>> {
+    let mut tx = db.begin().await?;
+
+    let claimed = sqlx::query!(
+        r#"UPDATE job_queue SET status = 'running', heartbeat = NOW()
+           WHERE id = (
+               SELECT id FROM job_queue
+               WHERE NOT dead_letter AND (
+                   (status = 'new' AND run_at <= NOW())
+                   OR (status = 'running' AND heartbeat < NOW() - make_interval(secs => $1))
+               )
+               ORDER BY run_at
+               FOR UPDATE SKIP LOCKED
+               LIMIT 1
+           )
+           RETURNING id, queue, job, attempts"#,
+        STALE_HEARTBEAT_SECS as f64,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(claimed.map(|row| ClaimedJob {
+        id: row.id,
+        queue: row.queue,
+        job: row.job,
+        attempts: row.attempts,
+    }))
+}
+
+/// Runs the deployer call for a claimed job, keeping its `heartbeat` fresh for the duration so a
+/// crash mid-call doesn't leave the row stuck as `running` forever.
+async fn run_job(state: &State, claimed: ClaimedJob) {
+    let job_id = claimed.id;
+    let db = state.db.clone();
+    let heartbeat_task = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            let _ = sqlx::query!("UPDATE job_queue SET heartbeat = NOW() WHERE id = $1", job_id)
+                .execute(&db)
+                .await;
+        }
+    });
+
+    let result = process_job(state, &claimed).await;
+    heartbeat_task.abort();
+
+    match result {
+        Ok(destroyed) => {
+            if let Some(target) = &destroyed {
+                notify_destroyed(state, target).await;
+            }
+
+            if let Err(e) = sqlx::query!("DELETE FROM job_queue WHERE id = $1", job_id)
+                .execute(&state.db)
+                .await
+            {
+                error!("job_queue: failed to delete completed job {}: {:?}", job_id, e);
+            }
+        }
+        Err(e) => {
+            let attempts = claimed.attempts + 1;
+            let last_error = e.to_string();
+
+            if attempts >= MAX_ATTEMPTS {
+                error!(
+                    "job_queue: dead-lettering job {} ({}) after {} attempts: {}",
+                    job_id, claimed.queue, attempts, last_error
+                );
+                sqlx::query!(
+                    "UPDATE job_queue SET status = 'new', attempts = $2, dead_letter = true, last_error = $3 WHERE id = $1",
+                    job_id,
+                    attempts,
+                    last_error,
+                )
+                .execute(&state.db)
+                .await
+                .ok();
+            } else {
+                let backoff_secs = 2i64.saturating_pow(attempts as u32).min(MAX_BACKOFF_SECS);
+                debug!(
+                    "job_queue: retrying job {} ({}) in {}s (attempt {})",
+                    job_id, claimed.queue, backoff_secs, attempts
+                );
+                sqlx::query!(
+                    "UPDATE job_queue SET status = 'new', attempts = $2, run_at = NOW() + make_interval(secs => $3), last_error = $4 WHERE id = $1",
+                    job_id,
+                    attempts,
+                    backoff_secs as f64,
+                    last_error,
+                )
+                .execute(&state.db)
+                .await
+                .ok();
+            }
+        }
+    }
+}
+
+/// Tells the team their instance is gone. `destroy_challenge` (deployer-server) is the
+/// authoritative writer of `destroyed_at` now - by the time this runs, `process_job` has already
+/// polled this exact row to confirm it landed, so there's no lookup left to do here.
+async fn notify_destroyed(state: &State, target: &LiveDeployment) {
+    let Some(team_id) = target.team_id else {
+        return;
+    };
+
+    state
+        .notifications
+        .dispatch(
+            &state.db,
+            team_id,
+            &target.public_id,
+            crate::notifications::DeploymentEvent::Destroyed { deployment_id: target.public_id.clone() },
+        )
+        .await;
+}
+
+/// The non-destroyed `challenge_deployments` row for a `(challenge_id, team_id)` pair, if any.
+/// The deployer only ever lets one exist at a time, so this is the specific instance a `destroy`
+/// job is acting on.
+struct LiveDeployment {
+    id: i32,
+    public_id: String,
+    team_id: Option<i32>,
+}
+
+async fn find_live_deployment(
+    state: &State,
+    challenge_id: i32,
+    team_id: Option<i32>,
+) -> eyre::Result<Option<LiveDeployment>> {
+    let row = sqlx::query_as!(
+        LiveDeployment,
+        r#"SELECT id, public_id, team_id FROM challenge_deployments
+           WHERE challenge_id = $1 AND team_id IS NOT DISTINCT FROM $2 AND destroyed_at IS NULL"#,
+        challenge_id,
+        team_id,
+    )
+    .fetch_optional(&state.db)
+    .await?;
+
+    Ok(row)
+}
+
+async fn process_job(state: &State, claimed: &ClaimedJob) -> eyre::Result<Option<LiveDeployment>> {
+    let job: ChallengeJob = serde_json::from_value(claimed.job.clone())?;
+
+    let path = match claimed.queue.as_str() {
+        "deploy" => "api/challenge/deploy",
+        "destroy" => "api/challenge/destroy",
+        other => return Err(eyre::eyre!("unknown job_queue queue {:?}", other)),
+    };
+
+    // capture which row is live *before* asking the deployer to tear it down, so we can confirm
+    // and notify on this specific instance afterwards instead of any row this challenge/team
+    // pair has ever had destroyed
+    let target = if claimed.queue == "destroy" {
+        find_live_deployment(state, job.challenge_id, job.team_id).await?
+    } else {
+        None
+    };
+
+    reqwest::Client::new()
+        .post(format!("{}/{}", state.config.deployer_base, path))
+        .json(&job)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    if let Some(target) = &target {
+        wait_for_destroyed(state, target.id).await?;
+    }
+
+    Ok(target)
+}
+
+/// Blocks until the specific deployment row `process_job` captured actually has `destroyed_at`
+/// set, since the deployer's destroy endpoint only enqueues its own teardown job and returns
+/// immediately. Scoped to a single deployment id rather than `(challenge_id, team_id)` - that
+/// pair accumulates one row per deploy/destroy cycle, so an unscoped check would already be
+/// permanently true after the first-ever destroy for that pair. A timeout here just fails the
+/// job like any other error, which retries the whole destroy request - safe, since the
+/// deployer's endpoint is a no-op once the deployment is gone.
+async fn wait_for_destroyed(state: &State, deployment_id: i32) -> eyre::Result<()> {
+    for _ in 0..DESTROY_POLL_MAX_ATTEMPTS {
+        let destroyed = sqlx::query_scalar!(
+            r#"SELECT destroyed_at IS NOT NULL AS "destroyed!" FROM challenge_deployments WHERE id = $1"#,
+            deployment_id,
+        )
+        .fetch_one(&state.db)
+        .await?;
+
+        if destroyed {
+            return Ok(());
+        }
+
+        tokio::time::sleep(DESTROY_POLL_INTERVAL).await;
+    }
+
+    Err(eyre::eyre!(
+        "timed out waiting for deployer to destroy deployment {}",
+        deployment_id,
+    ))
+}
+
+/// Background worker loop: polls for due/overdue jobs and runs them. Safe to run several of
+/// these concurrently (claiming is done with `FOR UPDATE SKIP LOCKED`).
+pub async fn worker_loop(state: State) {
+    loop {
+        match claim_one(&state.db).await {
+            Ok(Some(job)) => {
+                debug!("job_queue: claimed job {} ({})", job.id, job.queue);
+                run_job(&state, job).await;
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => error!("job_queue: error claiming job: {:?}", e),
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Periodically enqueues `destroy` jobs for deployments that have passed their `expired_at`,
+/// replacing the old on-demand `DELETE /reap` admin endpoint.
+pub async fn reap_loop(state: State) {
+    loop {
+        if let Err(e) = reap_once(&state).await {
+            error!("job_queue: reap scan failed: {:?}", e);
+        }
+
+        tokio::time::sleep(REAP_POLL_INTERVAL).await;
+    }
+}
+
+async fn reap_once(state: &State) -> eyre::Result<()> {
+    let expired = sqlx::query!(
+        "SELECT challenge_id, team_id FROM challenge_deployments WHERE NOW() > expired_at AND destroyed_at IS NULL"
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    for row in expired {
+        enqueue(
+            &state.db,
+            "destroy",
+            &ChallengeJob {
+                challenge_id: row.challenge_id,
+                team_id: row.team_id,
+            },
+        )
+        .await?;
+    }
+
+    warn_impending_expiries(state).await?;
+
+    Ok(())
+}
+
+/// Claims (via the `expiry_warned` flag) and notifies every instanced deployment whose
+/// `expired_at` falls within `deployment_expiry_warning_secs`, exactly once per deployment.
+async fn warn_impending_expiries(state: &State) -> eyre::Result<()> {
+    let due = sqlx::query!(
+        r#"UPDATE challenge_deployments SET expiry_warned = true
+           WHERE NOT expiry_warned
+             AND destroyed_at IS NULL
+             AND team_id IS NOT NULL
+             AND expired_at IS NOT NULL
+             AND expired_at <= NOW() + make_interval(secs => $1)
+           RETURNING public_id, team_id AS "team_id!", expired_at AS "expired_at!""#,
+        state.config.deployment_expiry_warning_secs as f64,
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    for row in due {
+        let expires_in_secs = (row.expired_at - chrono::Utc::now().naive_utc())
+            .num_seconds()
+            .max(0);
+
+        state
+            .notifications
+            .dispatch(
+                &state.db,
+                row.team_id,
+                &row.public_id,
+                crate::notifications::DeploymentEvent::ExpiringSoon {
+                    deployment_id: row.public_id.clone(),
+                    expires_in_secs,
+                },
+            )
+            .await;
+    }
+
+    Ok(())
+}
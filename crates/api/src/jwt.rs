@@ -0,0 +1,53 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::JwtKeys, Result};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub team_id: String,
+    pub exp: usize,
+}
+
+pub fn generate_jwt(keys: &JwtKeys, team_id: &str, duration: Duration) -> Result<String> {
+    let claims = Claims {
+        team_id: team_id.to_string(),
+        exp: (Utc::now() + duration).timestamp() as usize,
+    };
+
+    Ok(encode(&Header::default(), &claims, &keys.encoding)?)
+}
+
+pub fn decode_jwt(keys: &JwtKeys, token: &str) -> Result<Claims> {
+    Ok(decode::<Claims>(token, &keys.decoding, &Validation::default())?.claims)
+}
+
+/// Claims for a short-lived attachment download link: scoped to one relative path under the
+/// attachment directory rather than a general session, so it can be handed out with a much
+/// shorter expiry than a login JWT without needing a separate signing key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachmentClaims {
+    pub team_id: String,
+    pub path: String,
+    pub exp: usize,
+}
+
+pub fn generate_attachment_token(
+    keys: &JwtKeys,
+    team_id: &str,
+    path: &str,
+    duration: Duration,
+) -> Result<String> {
+    let claims = AttachmentClaims {
+        team_id: team_id.to_string(),
+        path: path.to_string(),
+        exp: (Utc::now() + duration).timestamp() as usize,
+    };
+
+    Ok(encode(&Header::default(), &claims, &keys.encoding)?)
+}
+
+pub fn decode_attachment_token(keys: &JwtKeys, token: &str) -> Result<AttachmentClaims> {
+    Ok(decode::<AttachmentClaims>(token, &keys.decoding, &Validation::default())?.claims)
+}
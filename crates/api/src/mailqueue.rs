@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use lettre::{
+    message::{Message, MultiPart, SinglePart},
+    AsyncTransport,
+};
+use log::{debug, error};
+
+use crate::{config::State, DB};
+
+/// How long a `sending` row can go without a heartbeat before we assume the worker that claimed
+/// it died mid-send and the job is retried.
+const STALE_HEARTBEAT_SECS: i64 = 120;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_ATTEMPTS: i32 = 6;
+
+/// Exponential backoff before retrying a failed send: 1m, 5m, then capped at 30m.
+fn backoff_for(attempt: i32) -> chrono::Duration {
+    chrono::Duration::seconds(match attempt {
+        0 => 60,
+        1 => 5 * 60,
+        _ => 30 * 60,
+    })
+}
+
+/// Enqueues an email for the background worker to deliver, instead of sending it inline.
+pub async fn enqueue(
+    db: &DB,
+    recipient: &str,
+    subject: &str,
+    body_text: &str,
+    body_html: &str,
+) -> eyre::Result<()> {
+    sqlx::query!(
+        "INSERT INTO pending_emails (recipient, subject, body_text, body_html) VALUES ($1, $2, $3, $4)",
+        recipient,
+        subject,
+        body_text,
+        body_html,
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+struct ClaimedEmail {
+    id: i32,
+    recipient: String,
+    subject: String,
+    body_text: String,
+    body_html: String,
+    attempt: i32,
+}
+
+async fn claim_one(db: &DB) -> eyre::Result<Option<ClaimedEmail>> {
+    let mut tx = db.begin().await?;
+
+    let claimed = sqlx::query!(
+        r#"UPDATE pending_emails SET status = 'sending', heartbeat = NOW()
+           WHERE id = (
+               SELECT id FROM pending_emails
+               WHERE next_attempt_at <= NOW() AND (
+                   status = 'pending'
+                   OR (status = 'sending' AND heartbeat < NOW() - make_interval(secs => $1))
+               )
+               ORDER BY next_attempt_at
+               FOR UPDATE SKIP LOCKED
+               LIMIT 1
+           )
+           RETURNING id, recipient, subject, body_text, body_html, attempt"#,
+        STALE_HEARTBEAT_SECS as f64,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(claimed.map(|row| ClaimedEmail {
+        id: row.id,
+        recipient: row.recipient,
+        subject: row.subject,
+        body_text: row.body_text,
+        body_html: row.body_html,
+        attempt: row.attempt,
+    }))
+}
+
+async fn run_email(state: &State, email: ClaimedEmail) {
+    let result = try_send(state, &email).await;
+
+    match result {
+        Ok(()) => {
+            if let Err(e) = sqlx::query!("DELETE FROM pending_emails WHERE id = $1", email.id)
+                .execute(&state.db)
+                .await
+            {
+                error!("mailqueue: failed to delete sent email {}: {:?}", email.id, e);
+            }
+        }
+        Err(e) => {
+            let attempt = email.attempt + 1;
+            let last_error = e.to_string();
+
+            if attempt >= MAX_ATTEMPTS {
+                error!(
+                    "mailqueue: giving up on email {} to {} after {} attempts: {}",
+                    email.id, email.recipient, attempt, last_error
+                );
+                sqlx::query!(
+                    "UPDATE pending_emails SET status = 'failed', attempt = $2, last_error = $3 WHERE id = $1",
+                    email.id,
+                    attempt,
+                    last_error,
+                )
+                .execute(&state.db)
+                .await
+                .ok();
+            } else {
+                let next_attempt_at = chrono::Utc::now().naive_utc() + backoff_for(email.attempt);
+                debug!(
+                    "mailqueue: retrying email {} to {} at {} (attempt {})",
+                    email.id, email.recipient, next_attempt_at, attempt
+                );
+                sqlx::query!(
+                    "UPDATE pending_emails SET status = 'pending', attempt = $2, next_attempt_at = $3, last_error = $4 WHERE id = $1",
+                    email.id,
+                    attempt,
+                    next_attempt_at,
+                    last_error,
+                )
+                .execute(&state.db)
+                .await
+                .ok();
+            }
+        }
+    }
+}
+
+async fn try_send(state: &State, email: &ClaimedEmail) -> eyre::Result<()> {
+    let mailer = state
+        .email
+        .mailer
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("no mailer configured"))?;
+
+    let message = Message::builder()
+        .from(state.email.from_email.parse()?)
+        .to(email.recipient.parse()?)
+        .subject(&email.subject)
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(SinglePart::plain(email.body_text.clone()))
+                .singlepart(SinglePart::html(email.body_html.clone())),
+        )?;
+
+    mailer.send(message).await?;
+
+    Ok(())
+}
+
+/// Background worker loop: polls for due/overdue emails and delivers them, retrying failures
+/// with backoff. Safe to run several of these concurrently (claiming is done with
+/// `FOR UPDATE SKIP LOCKED`).
+pub async fn worker_loop(state: State) {
+    loop {
+        match claim_one(&state.db).await {
+            Ok(Some(email)) => {
+                debug!("mailqueue: claimed email {} to {}", email.id, email.recipient);
+                run_email(&state, email).await;
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => error!("mailqueue: error claiming email: {:?}", e),
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
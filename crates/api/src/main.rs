@@ -2,6 +2,7 @@ use axum::{http::HeaderValue, Router};
 use envconfig::Envconfig;
 use eyre::Context;
 use sqlx::postgres::PgPoolOptions;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tower_http::cors::{Any, CorsLayer};
 
 mod admin;
@@ -10,11 +11,21 @@ mod api;
 mod badges;
 mod config;
 mod db;
+mod deployment_watch;
 mod email;
 mod error;
 mod event;
 mod extractors;
+mod job_queue;
 mod jwt;
+mod mailqueue;
+mod migrator;
+mod notifications;
+mod notifier;
+mod scoreboard;
+mod submission_throttle;
+mod templates;
+mod watermark;
 
 use config::State;
 use db::DB;
@@ -35,7 +46,13 @@ async fn main() -> eyre::Result<()> {
         .connect(&cfg.database_url)
         .await?;
 
-    sqlx::migrate!("../../migrations").run(&pool).await?;
+    migrator::check_and_apply(&pool, cfg.auto_apply_migrations)
+        .await
+        .context("check database schema version")?;
+
+    let submission_throttle = submission_throttle::SubmissionThrottle::load(&pool)
+        .await
+        .context("seed submission throttle from recent submissions")?;
 
     let cors = CorsLayer::new()
         .allow_methods(Any)
@@ -43,28 +60,49 @@ async fn main() -> eyre::Result<()> {
         .allow_headers(Any);
     // .allow_credentials(true);
 
+    let tt = TaskTracker::new();
+    let ct = CancellationToken::new();
+
+    let state = State::new(config::StateInner {
+        email: email::EmailService::new(&cfg, pool.clone())
+            .context("load email templates")?,
+        attachment_service: attachments::AttachmentService::new(&cfg),
+        notifications: notifications::NotificationService::new(&cfg),
+        notifier: notifier::NotifierService::new(&cfg),
+        deployment_events: deployment_watch::channel(),
+        scoreboard_events: scoreboard::channel(),
+        submission_throttle,
+        config: cfg,
+        event,
+        db: pool,
+    });
+
+    tt.spawn(mailqueue::worker_loop(state.clone()));
+    tt.spawn(job_queue::worker_loop(state.clone()));
+    tt.spawn(job_queue::reap_loop(state.clone()));
+    tt.spawn(notifications::retry_loop(state.clone()));
+    tt.spawn(deployment_watch::poll_loop(state.clone()));
+    tt.spawn(submission_throttle::prune_loop(state.clone()));
+
     let app = Router::<State>::new()
         .nest("/api", api::router())
-        .with_state(State::new(config::StateInner {
-            email: email::EmailService::new(&cfg),
-            attachment_service: attachments::AttachmentService::new(&cfg),
-            config: cfg,
-            event,
-            db: pool,
-        }))
+        .with_state(state)
         .layer(cors);
 
     // run our app with hyper, listening globally on port 3333
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3333").await.unwrap();
     axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+        .with_graceful_shutdown(shutdown_signal(ct))
         .await
         .unwrap();
 
+    tt.close();
+    tt.wait().await;
+
     Ok(())
 }
 
-async fn shutdown_signal() {
+async fn shutdown_signal(ct: CancellationToken) {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
             .await
@@ -85,4 +123,6 @@ async fn shutdown_signal() {
         _ = ctrl_c => {},
         _ = terminate => {},
     }
+
+    ct.cancel();
 }
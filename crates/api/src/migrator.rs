@@ -0,0 +1,43 @@
+use std::collections::HashSet;
+
+use sqlx::migrate::Migrator;
+
+use crate::DB;
+
+/// Embeds the ordered `migrations/` directory at compile time, so a fresh Postgres can be
+/// brought up from nothing without a separate out-of-band `sqlx migrate run` step.
+pub static MIGRATOR: Migrator = sqlx::migrate!("../../migrations");
+
+/// Ensures the database is at the expected schema version before the rest of `StateInner` is
+/// built. With `auto_apply` the migrator runs any pending migrations itself; without it, pending
+/// migrations are a hard startup error instead of silent schema drift, and the operator is
+/// expected to run the `migrate` binary (or flip `AUTO_APPLY_MIGRATIONS`) first.
+pub async fn check_and_apply(db: &DB, auto_apply: bool) -> eyre::Result<()> {
+    if auto_apply {
+        MIGRATOR.run(db).await?;
+        return Ok(());
+    }
+
+    let applied: HashSet<i64> = sqlx::query_scalar!("SELECT version FROM _sqlx_migrations WHERE success")
+        .fetch_all(db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+
+    let pending: Vec<String> = MIGRATOR
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .map(|m| format!("{} {}", m.version, m.description))
+        .collect();
+
+    if !pending.is_empty() {
+        return Err(eyre::eyre!(
+            "database is missing {} migration(s): {} - run the `migrate` binary (or set AUTO_APPLY_MIGRATIONS=true) before starting the server",
+            pending.len(),
+            pending.join(", "),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,337 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::{config::Config, DB};
+
+const RETRY_POLL_INTERVAL: Duration = Duration::from_secs(20);
+const MAX_DELIVERY_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConnectionInfo {
+    Tcp { host: String, port: u16 },
+    Http { url: String },
+}
+
+/// A deployment lifecycle transition a team should be told about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeploymentEvent {
+    Ready {
+        deployment_id: String,
+        connections: Vec<ConnectionInfo>,
+    },
+    ExpiringSoon {
+        deployment_id: String,
+        expires_in_secs: i64,
+    },
+    Destroyed {
+        deployment_id: String,
+    },
+}
+
+impl DeploymentEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DeploymentEvent::Ready { .. } => "ready",
+            DeploymentEvent::ExpiringSoon { .. } => "expiring_soon",
+            DeploymentEvent::Destroyed { .. } => "destroyed",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            DeploymentEvent::Ready { deployment_id, connections } => {
+                let targets = connections
+                    .iter()
+                    .map(|c| match c {
+                        ConnectionInfo::Tcp { host, port } => format!("{host}:{port}"),
+                        ConnectionInfo::Http { url } => url.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Deployment `{deployment_id}` is ready: {targets}")
+            }
+            DeploymentEvent::ExpiringSoon { deployment_id, expires_in_secs } => {
+                format!("Deployment `{deployment_id}` expires in {expires_in_secs}s")
+            }
+            DeploymentEvent::Destroyed { deployment_id } => {
+                format!("Deployment `{deployment_id}` has been destroyed")
+            }
+        }
+    }
+}
+
+/// One push provider in the dispatcher - a webhook, web push, or the in-process SSE channel.
+/// Kept trait-object-friendly (`Vec<Arc<dyn NotificationProvider>>`) so providers can be added
+/// without touching the dispatch/retry plumbing.
+#[async_trait]
+pub trait NotificationProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, db: &DB, team_id: i32, event: &DeploymentEvent) -> eyre::Result<()>;
+}
+
+/// Generic Discord/Slack-style webhook - both accept `{"content": "..."}`.
+pub struct WebhookProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookProvider {
+    pub fn new(url: String) -> Self {
+        Self { client: reqwest::Client::new(), url }
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for WebhookProvider {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn send(&self, _db: &DB, _team_id: i32, event: &DeploymentEvent) -> eyre::Result<()> {
+        #[derive(Serialize)]
+        struct WebhookPayload {
+            content: String,
+        }
+
+        self.client
+            .post(&self.url)
+            .json(&WebhookPayload { content: event.message() })
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// VAPID web push. Subscriptions are opt-in and stored by the client under
+/// `teams.extra_data->'push_subscription'`; a team with none configured is a no-op, not an error.
+pub struct WebPushProvider {
+    private_key_pem: String,
+    subject: String,
+}
+
+impl WebPushProvider {
+    pub fn new(private_key_pem: String, subject: String) -> Self {
+        Self { private_key_pem, subject }
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for WebPushProvider {
+    fn name(&self) -> &'static str {
+        "web_push"
+    }
+
+    async fn send(&self, db: &DB, team_id: i32, event: &DeploymentEvent) -> eyre::Result<()> {
+        let row = sqlx::query!("SELECT extra_data FROM teams WHERE id = $1", team_id)
+            .fetch_one(db)
+            .await?;
+
+        let Some(subscription) = row.extra_data.get("push_subscription") else {
+            return Ok(());
+        };
+
+        let endpoint = subscription
+            .get("endpoint")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| eyre::eyre!("push subscription missing endpoint"))?;
+        let p256dh = subscription
+            .get("keys")
+            .and_then(|k| k.get("p256dh"))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| eyre::eyre!("push subscription missing p256dh key"))?;
+        let auth = subscription
+            .get("keys")
+            .and_then(|k| k.get("auth"))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| eyre::eyre!("push subscription missing auth key"))?;
+
+        let subscription_info = web_push::SubscriptionInfo::new(endpoint, p256dh, auth);
+
+        let mut sig_builder =
+            web_push::VapidSignatureBuilder::from_pem(self.private_key_pem.as_bytes(), &subscription_info)?;
+        sig_builder.add_claim("sub", self.subject.as_str());
+        let signature = sig_builder.build()?;
+
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "title": "Deployment update",
+            "body": event.message(),
+        }))?;
+
+        let mut builder = web_push::WebPushMessageBuilder::new(&subscription_info);
+        builder.set_payload(web_push::ContentEncoding::Aes128Gcm, &payload);
+        builder.set_vapid_signature(signature);
+
+        web_push::WebPushClient::new()?.send(builder.build()?).await?;
+
+        Ok(())
+    }
+}
+
+/// What an SSE subscriber actually receives - the team it's for plus the event itself, so the
+/// stream handler can filter to the connected team without a second channel per team.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreboardNotification {
+    pub team_id: i32,
+    pub event: DeploymentEvent,
+}
+
+/// Fans deployment events out to whichever teams currently have the scoreboard's event stream
+/// open. Delivery is best-effort - there's nothing to retry once nobody's listening.
+pub struct SseProvider {
+    sender: broadcast::Sender<ScoreboardNotification>,
+}
+
+impl SseProvider {
+    fn new() -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ScoreboardNotification> {
+        self.sender.subscribe()
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for SseProvider {
+    fn name(&self) -> &'static str {
+        "sse"
+    }
+
+    async fn send(&self, _db: &DB, team_id: i32, event: &DeploymentEvent) -> eyre::Result<()> {
+        let _ = self.sender.send(ScoreboardNotification { team_id, event: event.clone() });
+        Ok(())
+    }
+}
+
+/// The multi-provider dispatcher: every configured provider gets a delivery attempt recorded,
+/// and failures are picked back up by `retry_loop` with backoff instead of being dropped.
+pub struct NotificationService {
+    providers: Vec<Arc<dyn NotificationProvider>>,
+    pub sse: Arc<SseProvider>,
+}
+
+impl NotificationService {
+    pub fn new(config: &Config) -> Self {
+        let sse = Arc::new(SseProvider::new());
+        let mut providers: Vec<Arc<dyn NotificationProvider>> = vec![sse.clone()];
+
+        if let Some(webhook_url) = &config.deployment_notification_webhook_url {
+            providers.push(Arc::new(WebhookProvider::new(webhook_url.clone())));
+        }
+
+        if let Some(vapid_key) = &config.vapid_private_key_pem {
+            providers.push(Arc::new(WebPushProvider::new(vapid_key.clone(), config.vapid_subject.clone())));
+        }
+
+        Self { providers, sse }
+    }
+
+    pub async fn dispatch(&self, db: &DB, team_id: i32, deployment_id: &str, event: DeploymentEvent) {
+        for provider in &self.providers {
+            let result = provider.send(db, team_id, &event).await;
+
+            if let Err(e) = &result {
+                warn!("notifications: provider {} failed for team {}: {:?}", provider.name(), team_id, e);
+            }
+
+            let status = if result.is_ok() { "sent" } else { "failed" };
+            let last_error = result.err().map(|e| e.to_string());
+            let event_value = serde_json::to_value(&event).ok();
+
+            if let Err(e) = sqlx::query!(
+                r#"INSERT INTO notification_deliveries
+                    (team_id, deployment_id, provider, event_type, payload, status, attempts, last_error)
+                   VALUES ($1, $2, $3, $4, $5, $6, 1, $7)"#,
+                team_id,
+                deployment_id,
+                provider.name(),
+                event.event_type(),
+                event_value,
+                status,
+                last_error,
+            )
+            .execute(db)
+            .await
+            {
+                error!("notifications: failed to record delivery attempt: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Background loop: retries `failed` deliveries that are due, with linear backoff, dead-lettering
+/// after `MAX_DELIVERY_ATTEMPTS`.
+pub async fn retry_loop(state: crate::State) {
+    loop {
+        if let Err(e) = retry_once(&state).await {
+            error!("notifications: retry pass failed: {:?}", e);
+        }
+
+        tokio::time::sleep(RETRY_POLL_INTERVAL).await;
+    }
+}
+
+async fn retry_once(state: &crate::State) -> eyre::Result<()> {
+    let due = sqlx::query!(
+        r#"SELECT id, team_id, deployment_id, provider, payload, attempts
+           FROM notification_deliveries
+           WHERE status = 'failed' AND next_attempt_at <= NOW()
+           LIMIT 20"#
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    for row in due {
+        let Some(provider) = state.notifications.providers.iter().find(|p| p.name() == row.provider) else {
+            continue;
+        };
+        let Some(payload) = row.payload else { continue };
+        let Ok(event) = serde_json::from_value::<DeploymentEvent>(payload) else { continue };
+
+        let result = provider.send(&state.db, row.team_id, &event).await;
+        let attempts = row.attempts + 1;
+
+        if result.is_ok() {
+            sqlx::query!(
+                "UPDATE notification_deliveries SET status = 'sent', attempts = $2 WHERE id = $1",
+                row.id,
+                attempts,
+            )
+            .execute(&state.db)
+            .await?;
+        } else if attempts >= MAX_DELIVERY_ATTEMPTS {
+            sqlx::query!(
+                "UPDATE notification_deliveries SET status = 'dead', attempts = $2, last_error = $3 WHERE id = $1",
+                row.id,
+                attempts,
+                result.unwrap_err().to_string(),
+            )
+            .execute(&state.db)
+            .await?;
+        } else {
+            let backoff_secs = 30i64 * attempts as i64;
+            sqlx::query!(
+                r#"UPDATE notification_deliveries
+                   SET attempts = $2, next_attempt_at = NOW() + make_interval(secs => $3), last_error = $4
+                   WHERE id = $1"#,
+                row.id,
+                attempts,
+                backoff_secs as f64,
+                result.unwrap_err().to_string(),
+            )
+            .execute(&state.db)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
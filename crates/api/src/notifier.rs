@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::config::Config;
+
+/// A scoreboard-wide occurrence sinks may care about - distinct from [`crate::notifications`],
+/// which is per-team deployment lifecycle delivery with persistence/retry. These are fire-and-forget
+/// and not tied to any one team's inbox.
+#[derive(Debug, Clone)]
+pub enum Event {
+    FirstBlood { team: String, challenge: String },
+    Solve { team: String, challenge: String },
+    DeploymentReady { team: String, deployment: String },
+    FlagLeaked { submitting_team: String, attributed_team: String, challenge: String },
+    EventStart,
+    EventEnd,
+}
+
+impl Event {
+    fn message(&self) -> String {
+        match self {
+            Event::FirstBlood { team, challenge } => {
+                format!("Congrats to `{team}` for first blooding `{challenge}`!")
+            }
+            Event::Solve { team, challenge } => {
+                format!("`{team}` solved `{challenge}`")
+            }
+            Event::DeploymentReady { team, deployment } => {
+                format!("`{team}`'s deployment `{deployment}` is ready")
+            }
+            Event::FlagLeaked { submitting_team, attributed_team, challenge } => {
+                format!(
+                    "`{submitting_team}` submitted a flag for `{challenge}` watermarked for `{attributed_team}` - possible leak"
+                )
+            }
+            Event::EventStart => "The event has started!".to_string(),
+            Event::EventEnd => "The event has ended!".to_string(),
+        }
+    }
+}
+
+/// One notification sink. Kept trait-object-friendly (`Vec<Arc<dyn Notifier>>`) so sinks can be
+/// added without touching `submit` or any other call site.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn notify(&self, event: &Event) -> eyre::Result<()>;
+}
+
+/// Discord/Slack-style webhook - both accept `{"content": "..."}`.
+pub struct DiscordWebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl DiscordWebhookSink {
+    pub fn new(url: String) -> Self {
+        Self { client: reqwest::Client::new(), url }
+    }
+}
+
+#[async_trait]
+impl Notifier for DiscordWebhookSink {
+    fn name(&self) -> &'static str {
+        "discord_webhook"
+    }
+
+    async fn notify(&self, event: &Event) -> eyre::Result<()> {
+        #[derive(Serialize)]
+        struct WebhookData {
+            content: String,
+        }
+
+        self.client
+            .post(&self.url)
+            .json(&WebhookData { content: event.message() })
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// A generic webhook with a user-supplied JSON body template, for services that don't speak the
+/// Discord/Slack `{"content": ...}` shape. `{{message}}` is substituted with the event's message,
+/// JSON-escaped so it stays valid inside the template's string context.
+pub struct JsonWebhookSink {
+    client: reqwest::Client,
+    url: String,
+    body_template: String,
+}
+
+impl JsonWebhookSink {
+    pub fn new(url: String, body_template: String) -> Self {
+        Self { client: reqwest::Client::new(), url, body_template }
+    }
+}
+
+#[async_trait]
+impl Notifier for JsonWebhookSink {
+    fn name(&self) -> &'static str {
+        "json_webhook"
+    }
+
+    async fn notify(&self, event: &Event) -> eyre::Result<()> {
+        let escaped_message = serde_json::to_string(&event.message())?;
+        let escaped_message = &escaped_message[1..escaped_message.len() - 1];
+        let body = self.body_template.replace("{{message}}", escaped_message);
+        let body: serde_json::Value = serde_json::from_str(&body)?;
+
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// Always-on fallback sink, so an event is never silently dropped on a deployment with no sinks
+/// configured.
+pub struct LogSink;
+
+#[async_trait]
+impl Notifier for LogSink {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn notify(&self, event: &Event) -> eyre::Result<()> {
+        info!("notifier: {}", event.message());
+        Ok(())
+    }
+}
+
+/// Fans scoreboard-wide events out to every configured sink. Best-effort - unlike
+/// [`crate::notifications::NotificationService`] there's no per-team delivery record to retry.
+pub struct NotifierService {
+    sinks: Vec<Arc<dyn Notifier>>,
+}
+
+impl NotifierService {
+    pub fn new(config: &Config) -> Self {
+        let mut sinks: Vec<Arc<dyn Notifier>> = vec![Arc::new(LogSink)];
+
+        if let Some(url) = &config.bloodbot_discord_webhook_url {
+            sinks.push(Arc::new(DiscordWebhookSink::new(url.clone())));
+        }
+
+        if let Some(url) = &config.notifier_webhook_url {
+            let body_template = config
+                .notifier_webhook_body_template
+                .clone()
+                .unwrap_or_else(|| r#"{"message": "{{message}}"}"#.to_string());
+            sinks.push(Arc::new(JsonWebhookSink::new(url.clone(), body_template)));
+        }
+
+        Self { sinks }
+    }
+
+    pub async fn dispatch(&self, event: Event) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.notify(&event).await {
+                warn!("notifier: sink {} failed: {:?}", sink.name(), e);
+            }
+        }
+    }
+}
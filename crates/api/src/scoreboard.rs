@@ -0,0 +1,16 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+/// A scoreboard-wide change a subscriber should see immediately, without polling `/challs/`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScoreboardEvent {
+    Solve { challenge: String, solves: i32 },
+    FirstBlood { team: String, challenge: String },
+}
+
+pub fn channel() -> broadcast::Sender<ScoreboardEvent> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
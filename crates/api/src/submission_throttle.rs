@@ -0,0 +1,138 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
+
+use crate::DB;
+
+/// How recent a wrong submission has to be to count towards the threshold.
+const WINDOW_SECS: i64 = 30;
+/// Wrong submissions within the window before a cooldown kicks in.
+const WRONG_THRESHOLD: u32 = 5;
+/// Cooldown length the first time a team trips the threshold on a challenge.
+const BASE_COOLDOWN_SECS: i64 = 30;
+/// Cooldown doubles on each repeat offense, capped here so a team can't get locked out forever.
+const MAX_COOLDOWN_SECS: i64 = 3600;
+/// How often the prune loop drops stale (team, challenge) entries.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy)]
+struct ThrottleState {
+    wrong_count: u32,
+    window_start: NaiveDateTime,
+    cooldown_until: Option<NaiveDateTime>,
+    offenses: u32,
+}
+
+/// Per-`(team_id, challenge_id)` submission throttling, mirroring how `tower_governor`'s limiter
+/// is held as in-memory state and pruned by a background thread in `api::challenges::router()` -
+/// `tower_governor` is IP-keyed and trivially bypassed across IPs/shared NAT, so this adds a
+/// team-keyed layer on top.
+pub struct SubmissionThrottle {
+    state: Mutex<HashMap<(i32, i32), ThrottleState>>,
+}
+
+impl SubmissionThrottle {
+    /// Seeds from recent incorrect submissions (the `submissions` table already records
+    /// `is_correct`) so a restart doesn't hand brute-forcers a clean slate mid-cooldown.
+    pub async fn load(db: &DB) -> crate::Result<Self> {
+        let now = Utc::now().naive_utc();
+        let since = now - ChronoDuration::seconds(WINDOW_SECS);
+
+        let rows = sqlx::query!(
+            r#"SELECT team_id, challenge_id, COUNT(*) AS "count!", MAX(created_at) AS "last!"
+               FROM submissions WHERE is_correct = false AND created_at > $1
+               GROUP BY team_id, challenge_id"#,
+            since,
+        )
+        .fetch_all(db)
+        .await?;
+
+        let mut state = HashMap::new();
+        for row in rows {
+            let wrong_count = row.count as u32;
+            let entry = if wrong_count >= WRONG_THRESHOLD {
+                ThrottleState {
+                    wrong_count: 0,
+                    window_start: row.last,
+                    cooldown_until: Some(row.last + ChronoDuration::seconds(BASE_COOLDOWN_SECS)),
+                    offenses: 1,
+                }
+            } else {
+                ThrottleState {
+                    wrong_count,
+                    window_start: row.last,
+                    cooldown_until: None,
+                    offenses: 0,
+                }
+            };
+            state.insert((row.team_id, row.challenge_id), entry);
+        }
+
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Seconds remaining until `(team_id, challenge_id)` may submit again, if currently cooling
+    /// down.
+    pub fn retry_after(&self, team_id: i32, challenge_id: i32) -> Option<i64> {
+        let now = Utc::now().naive_utc();
+        let state = self.state.lock().unwrap();
+        let entry = state.get(&(team_id, challenge_id))?;
+        let cooldown_until = entry.cooldown_until?;
+        (cooldown_until > now).then(|| (cooldown_until - now).num_seconds().max(1))
+    }
+
+    /// Records an incorrect submission, tripping (or escalating) a cooldown once the team crosses
+    /// `WRONG_THRESHOLD` wrong answers within `WINDOW_SECS`.
+    pub fn record_wrong(&self, team_id: i32, challenge_id: i32) {
+        let now = Utc::now().naive_utc();
+        let mut state = self.state.lock().unwrap();
+        let entry = state
+            .entry((team_id, challenge_id))
+            .or_insert(ThrottleState {
+                wrong_count: 0,
+                window_start: now,
+                cooldown_until: None,
+                offenses: 0,
+            });
+
+        if now - entry.window_start > ChronoDuration::seconds(WINDOW_SECS) {
+            entry.wrong_count = 0;
+            entry.window_start = now;
+        }
+        entry.wrong_count += 1;
+
+        if entry.wrong_count >= WRONG_THRESHOLD {
+            entry.offenses += 1;
+            let cooldown_secs =
+                (BASE_COOLDOWN_SECS * 2i64.pow(entry.offenses - 1)).min(MAX_COOLDOWN_SECS);
+            entry.cooldown_until = Some(now + ChronoDuration::seconds(cooldown_secs));
+            entry.wrong_count = 0;
+        }
+    }
+
+    /// A solve clears the throttle for that challenge - there's nothing left to brute-force.
+    pub fn clear(&self, team_id: i32, challenge_id: i32) {
+        self.state.lock().unwrap().remove(&(team_id, challenge_id));
+    }
+
+    /// Drops entries that are neither mid-window nor mid-cooldown.
+    fn retain_recent(&self) {
+        let now = Utc::now().naive_utc();
+        let mut state = self.state.lock().unwrap();
+        state.retain(|_, entry| {
+            entry.cooldown_until.is_some_and(|until| until > now)
+                || now - entry.window_start <= ChronoDuration::seconds(WINDOW_SECS)
+        });
+    }
+}
+
+/// Background loop: periodically prunes stale throttle entries, mirroring the governor limiter's
+/// own cleanup thread in `api::challenges::router()`.
+pub async fn prune_loop(state: crate::State) {
+    loop {
+        tokio::time::sleep(PRUNE_INTERVAL).await;
+        state.submission_throttle.retain_recent();
+    }
+}
@@ -0,0 +1,79 @@
+use crate::Result;
+
+/// Default plain-text/HTML template pairs, embedded at compile time so the event still has a
+/// branded-enough look even if `TEMPLATE_DIR` isn't set up.
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "verify_team.txt.tera",
+        include_str!("../templates/verify_team.txt.tera"),
+    ),
+    (
+        "verify_team.html.tera",
+        include_str!("../templates/verify_team.html.tera"),
+    ),
+    (
+        "verify_email_change.txt.tera",
+        include_str!("../templates/verify_email_change.txt.tera"),
+    ),
+    (
+        "verify_email_change.html.tera",
+        include_str!("../templates/verify_email_change.html.tera"),
+    ),
+    (
+        "resend_token.txt.tera",
+        include_str!("../templates/resend_token.txt.tera"),
+    ),
+    (
+        "resend_token.html.tera",
+        include_str!("../templates/resend_token.html.tera"),
+    ),
+    (
+        "login_code.txt.tera",
+        include_str!("../templates/login_code.txt.tera"),
+    ),
+    (
+        "login_code.html.tera",
+        include_str!("../templates/login_code.html.tera"),
+    ),
+];
+
+/// Renders the text/HTML parts of outgoing emails. Event organizers can drop
+/// `<name>.txt.tera`/`<name>.html.tera` files into `TEMPLATE_DIR` to override the look of a
+/// specific message; anything not overridden falls back to the built-in default.
+pub struct Templates {
+    tera: tera::Tera,
+}
+
+impl Templates {
+    pub fn load(template_dir: Option<&str>) -> Result<Self> {
+        let mut tera = match template_dir {
+            Some(dir) => {
+                let glob = format!("{}/**/*.tera", dir.trim_end_matches('/'));
+                match tera::Tera::new(&glob) {
+                    Ok(tera) => tera,
+                    Err(e) => {
+                        log::warn!("failed to load email templates from {}: {}", dir, e);
+                        tera::Tera::default()
+                    }
+                }
+            }
+            None => tera::Tera::default(),
+        };
+
+        for (name, source) in DEFAULT_TEMPLATES {
+            if !tera.get_template_names().any(|existing| existing == *name) {
+                tera.add_raw_template(name, source)?;
+            }
+        }
+
+        Ok(Self { tera })
+    }
+
+    /// Renders `{name}.txt.tera` and `{name}.html.tera` against the same context, returning
+    /// `(text, html)`.
+    pub fn render(&self, name: &str, ctx: &tera::Context) -> Result<(String, String)> {
+        let text = self.tera.render(&format!("{name}.txt.tera"), ctx)?;
+        let html = self.tera.render(&format!("{name}.html.tera"), ctx)?;
+        Ok((text, html))
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::Cursor;
+
+use bitstream_io::{BitRead, BitReader, LittleEndian};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Characters with a visually-ambiguous digit alternative, used to embed a per-team watermark
+/// into an otherwise-unchanged flag. `i` and `l` both map to `1`; that is never ambiguous, since
+/// `recover` compares each position against the base flag's own character.
+const LEET_TABLE: &[(char, char)] =
+    &[('a', '4'), ('e', '3'), ('i', '1'), ('o', '0'), ('s', '5'), ('t', '7'), ('l', '1')];
+
+fn leet_alt(c: char) -> Option<char> {
+    LEET_TABLE.iter().find(|(base, _)| *base == c).map(|(_, alt)| *alt)
+}
+
+/// How many bits of watermark a flag has room for - one per substitutable character.
+pub fn capacity(base_flag: &str) -> usize {
+    base_flag.chars().filter(|c| leet_alt(*c).is_some()).count()
+}
+
+/// Derives `num_bits` of keystream from `HMAC-SHA256(secret, challenge_id || team_id || counter)`,
+/// extending with additional counter-suffixed blocks if a flag needs more bits than one HMAC
+/// output provides. This is the pattern a team's watermark is expected to follow, so it's used
+/// both to issue a flag and to check whether a recovered bit pattern belongs to a given team.
+pub fn expected_bits(secret: &[u8], challenge_id: i32, team_id: i32, num_bits: usize) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(num_bits);
+    let mut counter: u32 = 0;
+
+    while bits.len() < num_bits {
+        // any key length is valid for HMAC, so this can never actually fail
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+        mac.update(&challenge_id.to_le_bytes());
+        mac.update(&team_id.to_le_bytes());
+        mac.update(&counter.to_le_bytes());
+        let block = mac.finalize().into_bytes();
+
+        let mut reader = BitReader::<_, LittleEndian>::new(Cursor::new(block.as_slice()));
+        while bits.len() < num_bits {
+            match reader.read_bit() {
+                Ok(bit) => bits.push(bit),
+                Err(_) => break,
+            }
+        }
+
+        counter += 1;
+    }
+
+    bits
+}
+
+/// Produces the flag this team should be issued: walks `base_flag` left to right and, at each
+/// substitutable character, applies the leet alternative iff the corresponding keystream bit is
+/// set.
+pub fn watermark_flag(secret: &[u8], challenge_id: i32, team_id: i32, base_flag: &str) -> String {
+    let bits = expected_bits(secret, challenge_id, team_id, capacity(base_flag));
+    let mut bit_iter = bits.into_iter();
+
+    base_flag
+        .chars()
+        .map(|c| match leet_alt(c) {
+            Some(alt) if bit_iter.next().unwrap_or(false) => alt,
+            _ => c,
+        })
+        .collect()
+}
+
+/// Checks a submission against `base_flag`, tolerating substitutions at watermarkable positions,
+/// and recovers the bit pattern it encodes. Never short-circuits on the first mismatching
+/// character, so a wrong flag doesn't take measurably less time to reject than a near-miss.
+///
+/// Returns `(valid, bits)`: `valid` is false if the lengths differ or any non-watermarked
+/// character doesn't match `base_flag`, and `bits` is the recovered watermark pattern (only
+/// meaningful when `valid` is true).
+pub fn recover(base_flag: &str, submitted: &str) -> (bool, Vec<bool>) {
+    let base_chars: Vec<char> = base_flag.chars().collect();
+    let sub_chars: Vec<char> = submitted.chars().collect();
+
+    if base_chars.len() != sub_chars.len() {
+        return (false, Vec::new());
+    }
+
+    let mut valid = true;
+    let mut bits = Vec::with_capacity(base_chars.len());
+
+    for (b, s) in base_chars.iter().zip(sub_chars.iter()) {
+        match leet_alt(*b) {
+            Some(alt) => {
+                let is_base = *s == *b;
+                let is_alt = *s == alt;
+                valid &= is_base || is_alt;
+                bits.push(is_alt);
+            }
+            None => {
+                valid &= *s == *b;
+            }
+        }
+    }
+
+    (valid, bits)
+}
+
+/// Constant-time comparison - used in place of `==` wherever a flag or watermark pattern is
+/// checked against attacker-controlled input, so a correct prefix doesn't return measurably faster
+/// than an incorrect one.
+pub fn constant_time_eq<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut matches = true;
+    for (x, y) in a.iter().zip(b.iter()) {
+        matches &= x == y;
+    }
+    matches
+}
+
+#[cfg(test)]
+mod watermark_tests {
+    use super::{capacity, expected_bits, recover, watermark_flag};
+
+    #[test]
+    fn expected_bits_is_deterministic_and_team_specific() {
+        let secret = b"test-secret";
+        let a = expected_bits(secret, 1, 1, 16);
+        let b = expected_bits(secret, 1, 1, 16);
+        let c = expected_bits(secret, 1, 2, 16);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn expected_bits_spans_multiple_hmac_blocks() {
+        // one HMAC-SHA256 block is 256 bits - ask for more to exercise the counter rollover
+        let bits = expected_bits(b"test-secret", 1, 1, 300);
+        assert_eq!(bits.len(), 300);
+    }
+
+    #[test]
+    fn recover_round_trips_a_watermarked_flag() {
+        let secret = b"test-secret";
+        let base_flag = "flag{leetspeak_test}";
+        let watermarked = watermark_flag(secret, 42, 7, base_flag);
+
+        let (valid, bits) = recover(base_flag, &watermarked);
+        assert!(valid);
+        assert_eq!(bits, expected_bits(secret, 42, 7, capacity(base_flag)));
+    }
+
+    #[test]
+    fn recover_rejects_a_non_watermarked_character_change() {
+        // "flag{...}" -> corrupt the literal brace, which has no leet alternative
+        let (valid, _) = recover("flag{test}", "flag[test}");
+        assert!(!valid);
+    }
+
+    #[test]
+    fn recover_rejects_mismatched_length() {
+        let (valid, bits) = recover("flag{test}", "flag{test}x");
+        assert!(!valid);
+        assert!(bits.is_empty());
+    }
+
+    #[test]
+    fn recover_accepts_either_base_or_leet_form_at_each_position() {
+        // "test" -> t,e,s,t all have leet alternatives (7,3,5,7); only the 'e' is substituted here
+        let (valid, bits) = recover("test", "t3st");
+        assert!(valid);
+        assert_eq!(bits, vec![false, true, false, false]);
+    }
+}
@@ -4,7 +4,10 @@ use std::{
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 use bollard::auth::DockerCredentials;
@@ -16,10 +19,12 @@ use deployer_common::challenge::{
 use deployer_common::uploader::Uploader;
 use dialoguer::{Select, theme::SimpleTheme};
 use eyre::{Result, eyre};
+use futures_util::StreamExt;
 use reqwest::{Url, cookie::Jar};
 use rustyline::DefaultEditor;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::sync::{RwLock, Semaphore};
 use walkdir::WalkDir;
 
 #[derive(Debug, Parser)]
@@ -57,6 +62,30 @@ enum Commands {
         local: bool,
     },
 
+    /// Builds the selected challenges one at a time, timing each pull/build/push phase, and
+    /// appends a structured report (with environment metadata) to a JSON file for tracking
+    /// build performance across machines or over time.
+    Bench {
+        #[arg()]
+        paths: Vec<PathBuf>,
+
+        /// Specifies which build group to use
+        #[arg(short = 'g', long)]
+        build_group: Option<String>,
+
+        /// Builds all challenges regardless of build group
+        #[arg(short, long)]
+        all: bool,
+
+        /// Skip pulling from and pushing to the registry, only time the build
+        #[arg(short, long, default_value_t = false)]
+        local: bool,
+
+        /// Report file to append this run's results to
+        #[arg(short, long, default_value = "bench.json")]
+        output: PathBuf,
+    },
+
     Platform {
         #[command(subcommand)]
         command: PlatformCommands,
@@ -96,6 +125,192 @@ enum PlatformCommands {
         token_expiration: Option<String>,
     },
 }
+
+/// Default number of challenges a single endpoint will build at once when
+/// `DOCKER_ENDPOINT_CONCURRENCY` isn't set.
+const DEFAULT_ENDPOINT_CONCURRENCY: usize = 4;
+
+/// One Docker daemon in the build farm: its client handle, a concurrency limit (so we don't
+/// slam a small endpoint with every challenge at once), and an in-flight counter the scheduler
+/// reads to find the least-loaded endpoint.
+struct ConfiguredEndpoint {
+    docker: bollard::Docker,
+    in_flight: AtomicUsize,
+    permits: Arc<Semaphore>,
+}
+
+/// Connects to a single Docker endpoint, supporting `unix://` paths in addition to the
+/// `tcp://`/`http://` addresses `bollard::Docker::connect_with_http` already understands.
+fn connect_endpoint(addr: &str) -> Result<bollard::Docker> {
+    if let Some(path) = addr.strip_prefix("unix://") {
+        Ok(bollard::Docker::connect_with_unix(path, 120, bollard::API_DEFAULT_VERSION)?)
+    } else {
+        Ok(bollard::Docker::connect_with_http(addr, 120, bollard::API_DEFAULT_VERSION)?)
+    }
+}
+
+/// Schedules challenge builds across a pool of Docker endpoints, picking whichever endpoint
+/// currently has the fewest builds running on it. Reads `DOCKER_ENDPOINTS` (comma-separated
+/// `unix://`/`tcp://`/`http://` addresses) and falls back to the local daemon when unset, so
+/// existing single-daemon setups keep working unchanged.
+struct BuildScheduler {
+    endpoints: Arc<RwLock<Vec<Arc<ConfiguredEndpoint>>>>,
+}
+
+impl BuildScheduler {
+    fn from_env() -> Result<Self> {
+        let concurrency = env::var("DOCKER_ENDPOINT_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_ENDPOINT_CONCURRENCY);
+
+        let addrs: Vec<String> = env::var("DOCKER_ENDPOINTS")
+            .ok()
+            .map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        let endpoints = if addrs.is_empty() {
+            vec![Arc::new(ConfiguredEndpoint {
+                docker: bollard::Docker::connect_with_local_defaults()?,
+                in_flight: AtomicUsize::new(0),
+                permits: Arc::new(Semaphore::new(concurrency)),
+            })]
+        } else {
+            addrs
+                .into_iter()
+                .map(|addr| {
+                    Ok(Arc::new(ConfiguredEndpoint {
+                        docker: connect_endpoint(&addr)?,
+                        in_flight: AtomicUsize::new(0),
+                        permits: Arc::new(Semaphore::new(concurrency)),
+                    }))
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        Ok(Self {
+            endpoints: Arc::new(RwLock::new(endpoints)),
+        })
+    }
+
+    /// Picks the least-loaded endpoint and reserves a build slot on it. The returned guard
+    /// decrements the in-flight counter and releases the semaphore permit on drop.
+    async fn acquire(&self) -> EndpointGuard {
+        let endpoint = {
+            let endpoints = self.endpoints.read().await;
+            endpoints
+                .iter()
+                .min_by_key(|e| e.in_flight.load(Ordering::Relaxed))
+                .expect("at least one docker endpoint must be configured")
+                .clone()
+        };
+
+        let permit = endpoint
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("endpoint semaphore is never closed");
+        endpoint.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        EndpointGuard {
+            endpoint,
+            _permit: permit,
+        }
+    }
+}
+
+/// Holds a build slot on a [`ConfiguredEndpoint`] for the duration of a single challenge build.
+struct EndpointGuard {
+    endpoint: Arc<ConfiguredEndpoint>,
+    _permit: tokio::sync::OwnedSemaphorePermit,
+}
+
+impl Drop for EndpointGuard {
+    fn drop(&mut self) {
+        self.endpoint.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Environment metadata recorded alongside a bench run, so a report can explain *why* build
+/// times differ across machines instead of just *that* they differ.
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvironmentInfo {
+    hostname: String,
+    os: String,
+    cpu_model: String,
+    cpu_cores: usize,
+    total_ram_bytes: u64,
+    docker_server_version: Option<String>,
+    git_commit: Option<String>,
+    git_branch: Option<String>,
+}
+
+/// Runs `cmd` with `args` in `cwd` and returns trimmed stdout, or `None` if the command isn't
+/// available or fails - bench metadata is best-effort and shouldn't block a run.
+fn run_capture(cmd: &str, args: &[&str], cwd: &Path) -> Option<String> {
+    let output = std::process::Command::new(cmd).args(args).current_dir(cwd).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    let trimmed = text.trim();
+    if trimmed.is_empty() { None } else { Some(trimmed.to_owned()) }
+}
+
+/// Parses the first `key : value` line out of a `/proc`-style info file, e.g. `cpuinfo`'s
+/// `model name` or `meminfo`'s `MemTotal`.
+fn proc_field(path: &str, key: &str) -> Option<String> {
+    let contents = fs::read_to_string(path).ok()?;
+    contents
+        .lines()
+        .find(|line| line.starts_with(key))
+        .and_then(|line| line.split(':').nth(1))
+        .map(|v| v.trim().to_owned())
+}
+
+async fn collect_environment_info(docker: &bollard::Docker) -> EnvironmentInfo {
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+
+    let cpu_model = proc_field("/proc/cpuinfo", "model name").unwrap_or_else(|| "unknown".to_owned());
+    let total_ram_bytes = proc_field("/proc/meminfo", "MemTotal")
+        .and_then(|v| v.split_whitespace().next().map(str::to_owned))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+        .unwrap_or(0);
+
+    EnvironmentInfo {
+        hostname: run_capture("hostname", &[], &cwd).unwrap_or_else(|| "unknown".to_owned()),
+        os: env::consts::OS.to_owned(),
+        cpu_model,
+        cpu_cores: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        total_ram_bytes,
+        docker_server_version: docker.version().await.ok().and_then(|v| v.version),
+        git_commit: run_capture("git", &["rev-parse", "HEAD"], &cwd),
+        git_branch: run_capture("git", &["rev-parse", "--abbrev-ref", "HEAD"], &cwd),
+    }
+}
+
+/// Per-challenge phase timings for one bench run. A phase is `None` when it was skipped (e.g.
+/// `pull`/`push` under `--local`) or did not complete because it or an earlier phase failed -
+/// the failure itself goes in `error`.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeTiming {
+    chall_id: String,
+    pull_ms: Option<u128>,
+    build_ms: Option<u128>,
+    push_ms: Option<u128>,
+    total_ms: u128,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BenchRun {
+    started_at: chrono::DateTime<chrono::Utc>,
+    environment: EnvironmentInfo,
+    challenges: Vec<ChallengeTiming>,
+}
+
 // todo case sensitive or not?
 fn search_for(dir: &Path, filenames: &[&str]) -> Option<PathBuf> {
     for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
@@ -270,6 +485,11 @@ async fn main() -> Result<()> {
                 }),
                 cap_add: None,
                 privileged: None,
+                readiness: None,
+                security: Default::default(),
+                registry: None,
+                cache_from: None,
+                cache_to: None,
             };
 
             let chall = Challenge {
@@ -299,6 +519,7 @@ async fn main() -> Result<()> {
                 provide: None,
                 host: None,
                 instance_lifetime: None,
+                verify: None,
             };
 
             path.push("challenge.toml");
@@ -324,43 +545,212 @@ async fn main() -> Result<()> {
                 println!("{}", chall.chall.id)
             }
 
-            let ctx = DeployableContext {
-                docker: bollard::Docker::connect_with_local_defaults()?,
-                // TODO if something not found, default to None
-                docker_credentials: {
-                    if local {
-                        None
-                    } else {
-                        Some(DockerCredentials {
-                            username: Some(env::var("DOCKER_USERNAME")?),
-                            password: Some(env::var("DOCKER_PASSWORD")?),
-                            email: None,
-                            serveraddress: Some(env::var("DOCKER_SERVERADDRESS")?),
-                            ..Default::default()
-                        })
-                    }
-                },
-                image_prefix: "".to_string(),
-                repo: env::var("DOCKER_REPO")?,
+            let docker_credentials = if local {
+                None
+            } else {
+                Some(DockerCredentials {
+                    username: Some(env::var("DOCKER_USERNAME")?),
+                    password: Some(env::var("DOCKER_PASSWORD")?),
+                    email: None,
+                    serveraddress: Some(env::var("DOCKER_SERVERADDRESS")?),
+                    ..Default::default()
+                })
             };
+            let repo = env::var("DOCKER_REPO")?;
 
+            let scheduler = Arc::new(BuildScheduler::from_env()?);
+
+            let mut handles = Vec::with_capacity(valid_challs.len());
             for chall in valid_challs {
-                println!("building chall {}", chall.chall.id);
-                if !local {
-                    chall.pull(&ctx).await?;
-                }
-                match chall.build(&ctx).await {
-                    Ok(_) => {
+                let scheduler = scheduler.clone();
+                let docker_credentials = docker_credentials.clone();
+                let repo = repo.clone();
+
+                handles.push(tokio::spawn(async move {
+                    let guard = scheduler.acquire().await;
+                    let ctx = DeployableContext {
+                        docker: guard.endpoint.docker.clone(),
+                        docker_credentials,
+                        registry_credentials: Vec::new(),
+                        image_prefix: "".to_string(),
+                        repo,
+                        experimental: Default::default(),
+                    };
+
+                    println!("building chall {}", chall.chall.id);
+                    let result: Result<()> = async {
+                        if !local {
+                            chall.pull(&ctx).await?;
+                        }
+                        if let Some(containers) = &chall.chall.container {
+                            for ct in containers.keys() {
+                                let Some(stream) = chall.build_ct_stream(&ctx, ct).await? else {
+                                    continue;
+                                };
+                                tokio::pin!(stream);
+                                // forward each build step as it arrives instead of buffering the
+                                // whole build, so an error frame fails the build immediately
+                                // rather than only after the image finishes
+                                while let Some(step) = stream.next().await {
+                                    let step = step?;
+                                    if let Some(error) = &step.error {
+                                        return Err(eyre!(
+                                            "build error for chall {} ({ct}): {error}",
+                                            chall.chall.id
+                                        ));
+                                    }
+                                }
+                            }
+                        }
+                        if let Some(report) = chall.verify(&ctx).await? {
+                            if !report.passed {
+                                return Err(eyre!(
+                                    "solver verification failed for chall {}: flag not found in output (stdout: {:?}, stderr: {:?})",
+                                    chall.chall.id,
+                                    report.stdout,
+                                    report.stderr
+                                ));
+                            }
+                            println!(
+                                "verified chall {} ({}ms)",
+                                chall.chall.id, report.duration_ms
+                            );
+                        }
                         if !local {
                             println!("pushing chall {}", chall.chall.id);
                             chall.push(&ctx).await?;
                         } else {
                             println!("skipping pushing chall {} to registry due to local flag being set", chall.chall.id);
                         }
+                        Ok(())
                     }
-                    Err(e) => eprintln!("failed to build {}: {e:?}", chall.chall.id),
-                };
+                    .await;
+
+                    (chall.chall.id, result)
+                }));
+            }
+
+            // each task reports its own result so one endpoint/challenge failing doesn't abort
+            // builds still running on the rest of the farm
+            let mut failures = 0;
+            for handle in handles {
+                let (id, result) = handle.await?;
+                match result {
+                    Ok(()) => println!("built {id}"),
+                    Err(e) => {
+                        failures += 1;
+                        eprintln!("failed to build {id}: {e:?}");
+                    }
+                }
+            }
+
+            if failures > 0 {
+                return Err(eyre!("{failures} challenge(s) failed to build"));
+            }
+        }
+        Commands::Bench {
+            paths,
+            build_group,
+            all,
+            local,
+            output,
+        } => {
+            let valid_challs: Vec<DeployableChallenge> = get_all_challs(&paths)
+                .filter(|c| c.chall.container.is_some())
+                .filter(|c| all || c.chall.build_group == build_group)
+                .collect();
+            println!("Benchmarking following challenges:");
+            for chall in &valid_challs {
+                println!("{}", chall.chall.id)
             }
+
+            let docker_credentials = if local {
+                None
+            } else {
+                Some(DockerCredentials {
+                    username: Some(env::var("DOCKER_USERNAME")?),
+                    password: Some(env::var("DOCKER_PASSWORD")?),
+                    email: None,
+                    serveraddress: Some(env::var("DOCKER_SERVERADDRESS")?),
+                    ..Default::default()
+                })
+            };
+            let repo = if local { String::new() } else { env::var("DOCKER_REPO")? };
+
+            // a single local handle, run sequentially - we're timing build performance, not
+            // throughput, so concurrent builds on the farm would just skew the numbers
+            let docker = bollard::Docker::connect_with_local_defaults()?;
+            let environment = collect_environment_info(&docker).await;
+
+            let ctx = DeployableContext {
+                docker,
+                docker_credentials,
+                registry_credentials: Vec::new(),
+                image_prefix: "".to_string(),
+                repo,
+                experimental: Default::default(),
+            };
+
+            let mut timings = Vec::with_capacity(valid_challs.len());
+            for chall in valid_challs {
+                println!("benching chall {}", chall.chall.id);
+                let mut pull_ms = None;
+                let mut build_ms = None;
+                let mut push_ms = None;
+                let mut error = None;
+                let start = std::time::Instant::now();
+
+                let result: Result<()> = async {
+                    if !local {
+                        let t = std::time::Instant::now();
+                        chall.pull(&ctx).await?;
+                        pull_ms = Some(t.elapsed().as_millis());
+                    }
+
+                    let t = std::time::Instant::now();
+                    chall.build(&ctx).await?;
+                    build_ms = Some(t.elapsed().as_millis());
+
+                    if !local {
+                        let t = std::time::Instant::now();
+                        chall.push(&ctx).await?;
+                        push_ms = Some(t.elapsed().as_millis());
+                    }
+
+                    Ok(())
+                }
+                .await;
+
+                if let Err(e) = result {
+                    eprintln!("failed to bench {}: {e:?}", chall.chall.id);
+                    error = Some(e.to_string());
+                }
+
+                timings.push(ChallengeTiming {
+                    chall_id: chall.chall.id,
+                    pull_ms,
+                    build_ms,
+                    push_ms,
+                    total_ms: start.elapsed().as_millis(),
+                    error,
+                });
+            }
+
+            let run = BenchRun {
+                started_at: chrono::Utc::now(),
+                environment,
+                challenges: timings,
+            };
+
+            let mut runs: Vec<BenchRun> = if output.exists() {
+                serde_json::from_str(&fs::read_to_string(&output)?).unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            runs.push(run);
+
+            fs::write(&output, serde_json::to_string_pretty(&runs)?)?;
+            println!("Wrote bench report to {}", output.to_string_lossy());
         }
         Commands::Platform { command } => match command {
             PlatformCommands::Update {
@@ -407,6 +797,17 @@ async fn main() -> Result<()> {
 
                 let uploader = Uploader::from_env().await;
 
+                // only needed for `Attachment::FromContainer`, which extracts from a locally
+                // built image rather than the challenge source tree
+                let ctx = DeployableContext {
+                    docker: bollard::Docker::connect_with_local_defaults()?,
+                    docker_credentials: None,
+                    registry_credentials: Vec::new(),
+                    image_prefix: "".to_string(),
+                    repo: env::var("DOCKER_REPO").unwrap_or_default(),
+                    experimental: Default::default(),
+                };
+
                 for ref dc in get_all_challs(&paths).filter(|c| c.chall.build_group == build_group)
                 {
                     println!("Processing chall {}", dc.chall.name);
@@ -414,7 +815,7 @@ async fn main() -> Result<()> {
                     let attachments = if null_attachments {
                         HashMap::new()
                     } else {
-                        dc.push_attachments(&uploader)
+                        dc.push_attachments(&ctx, &uploader)
                             .await?
                     };
                     client
@@ -1,16 +1,24 @@
-use bollard::query_parameters::CreateImageOptionsBuilder;
+use bollard::models::ContainerCreateBody;
+use bollard::query_parameters::{
+    CreateContainerOptionsBuilder, CreateExecOptions, CreateImageOptionsBuilder,
+    DownloadFromContainerOptionsBuilder, RemoveContainerOptionsBuilder, StartContainerOptions,
+    StartExecOptions,
+};
 use eyre::{Context, Result, eyre};
 use flate2::{Compression, write::GzEncoder};
 use log::info;
+use regex::Regex;
 use serde_with::{DisplayFromStr, serde_as};
 use std::{
     collections::HashMap,
     fs::{self, File as StdFile},
-    io::Read,
-    path::PathBuf,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
 };
+use walkdir::WalkDir;
 
-use futures_util::StreamExt;
+use futures_util::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use tempdir::TempDir;
 use tokio::fs::File;
@@ -41,6 +49,7 @@ pub struct Challenge {
     pub bump_seed: u64,
     pub host: Option<String>,
     pub instance_lifetime: Option<u64>,
+    pub verify: Option<Verify>,
 }
 
 fn is_zero(x: &u64) -> bool {
@@ -57,21 +66,111 @@ pub struct PointRange {
     pub max: i32,
 }
 
+/// An automated solve run against a freshly built image, so CI can catch a challenge that
+/// doesn't actually yield its own flag before it ships.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Verify {
+    // key into `Challenge::container` whose built image the solve command is exec'd against
+    pub container: String,
+    pub cmd: Vec<String>,
+    #[serde(default = "default_verify_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_verify_timeout_secs() -> u64 {
+    30
+}
+
+/// Result of running a [`Verify`] against a built image.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub passed: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u128,
+}
+
 impl Challenge {
     pub fn image_id(&self, ctx: &DeployableContext, ct: &str) -> String {
+        let repo = self
+            .container_of(ct)
+            .and_then(|container| container.registry.as_ref())
+            .and_then(|registry| registry.serveraddress.as_deref())
+            .unwrap_or(&ctx.repo);
+
         if ct == "default" {
-            format!("{}/{}{}", ctx.repo, ctx.image_prefix, self.id)
+            format!("{}/{}{}", repo, ctx.image_prefix, self.id)
         } else {
-            format!("{}/{}{}-{}", ctx.repo, ctx.image_prefix, self.id, ct)
+            format!("{}/{}{}-{}", repo, ctx.image_prefix, self.id, ct)
         }
     }
 
+    fn container_of(&self, ct: &str) -> Option<&Container> {
+        self.container.as_ref()?.get(ct)
+    }
+
+    // resolves which credentials to push/pull `ct`'s image with: `container.registry` overrides
+    // the context default when set; if it specifies a bare `serveraddress` (no username/password/
+    // identitytoken of its own), that address is used to look up the matching full credentials in
+    // `ctx.registry_credentials` instead, so challenges only need to name a registry they don't
+    // personally hold credentials for.
+    fn registry_credentials(&self, ctx: &DeployableContext, ct: &str) -> Option<bollard::auth::DockerCredentials> {
+        let Some(registry) = self.container_of(ct).and_then(|container| container.registry.as_ref()) else {
+            return ctx.docker_credentials.clone();
+        };
+
+        let host_only = registry.username.is_none()
+            && registry.password.is_none()
+            && registry.identitytoken.is_none();
+
+        if host_only {
+            if let Some(server) = &registry.serveraddress {
+                if let Some(matched) = ctx
+                    .registry_credentials
+                    .iter()
+                    .find(|candidate| candidate.serveraddress.as_deref() == Some(server.as_str()))
+                {
+                    return Some(matched.clone());
+                }
+            }
+        }
+
+        Some(registry.clone())
+    }
+
+    // resolves `ct`'s effective BuildKit registry cache refs as (cache_from, cache_to): an
+    // explicit `Container::cache_from`/`cache_to` wins, else the context-wide default in
+    // `ctx.experimental`, else (so a challenge gets incremental caching without any chall.toml
+    // changes) a single ref derived from the image id. Returns empty refs when BuildKit isn't
+    // enabled, since the classic builder doesn't understand registry cache import/export.
+    fn build_cache_refs(&self, ctx: &DeployableContext, ct: &str) -> (Vec<String>, Vec<String>) {
+        if !ctx.experimental.use_docker_buildkit {
+            return (Vec::new(), Vec::new());
+        }
+
+        let container = self.container_of(ct);
+        let default_ref = format!("type=registry,ref={}-buildcache", self.image_id(ctx, ct));
+
+        let cache_from = container
+            .and_then(|c| c.cache_from.clone())
+            .filter(|refs| !refs.is_empty())
+            .or_else(|| (!ctx.experimental.cache_from.is_empty()).then(|| ctx.experimental.cache_from.clone()))
+            .unwrap_or_else(|| vec![default_ref.clone()]);
+
+        let cache_to = container
+            .and_then(|c| c.cache_to.clone())
+            .filter(|refs| !refs.is_empty())
+            .or_else(|| (!ctx.experimental.cache_to.is_empty()).then(|| ctx.experimental.cache_to.clone()))
+            .unwrap_or_else(|| vec![format!("{},mode=max", default_ref)]);
+
+        (cache_from, cache_to)
+    }
+
     pub async fn push_ct(&self, ctx: &DeployableContext, ct: &str) -> Result<()> {
-        // TODO: support credentials
         let mut push = ctx.docker.push_image(
             &self.image_id(ctx, ct),
             None::<bollard::query_parameters::PushImageOptions>,
-            ctx.docker_credentials.clone(),
+            self.registry_credentials(ctx, ct),
         );
 
         while let Some(push_step) = push.next().await {
@@ -100,7 +199,7 @@ impl Challenge {
             .build();
         let mut pull = ctx
             .docker
-            .create_image(Some(options), None, ctx.docker_credentials.clone());
+            .create_image(Some(options), None, self.registry_credentials(ctx, ct));
 
         while let Some(pull_step) = pull.next().await {
             let pull_step = pull_step.context("Docker image pull error")?;
@@ -130,6 +229,17 @@ pub enum Flag {
     File { file: PathBuf },
 }
 
+impl Flag {
+    /// Resolves the real flag value, reading `file` off disk (relative to the challenge root)
+    /// for the `File` variant.
+    pub fn resolve(&self, root: &Path) -> Result<String> {
+        Ok(match self {
+            Self::Raw(flag) => flag.clone(),
+            Self::File { file } => fs::read_to_string(root.join(file))?.trim().to_string(),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Attachment {
@@ -146,6 +256,14 @@ pub enum Attachment {
         #[serde(default)]
         exclude: Option<Vec<PathBuf>>,
     },
+    // pulls `path` out of a throwaway container created from the image built for `container`
+    // (a key into `Challenge::container`), for artifacts the build produces rather than ones
+    // already sitting in the challenge source tree (e.g. a flag-embedded compiled binary)
+    FromContainer {
+        container: String,
+        path: PathBuf,
+        r#as: String,
+    },
 }
 
 fn default_archive_name() -> String {
@@ -163,6 +281,99 @@ pub struct Container {
     pub expose: Option<HashMap<u16, ExposeType>>,
     pub cap_add: Option<Vec<String>>,
     pub privileged: Option<bool>,
+    // checked by the deployer before the container's routes are published
+    pub readiness: Option<ReadinessProbe>,
+    #[serde(default)]
+    pub security: SecurityOptions,
+    // overrides `ctx.docker_credentials` for this image's push/pull. Set just `serveraddress` to
+    // pick matching credentials out of `ctx.registry_credentials` instead of duplicating them here.
+    pub registry: Option<bollard::auth::DockerCredentials>,
+    // overrides `ctx.experimental`'s context-wide BuildKit cache refs for this container's build.
+    // Each entry is a full cache ref string, e.g. "type=registry,ref=myrepo/chall-buildcache".
+    // Only used when `ExperimentalOptions::use_docker_buildkit` is enabled.
+    pub cache_from: Option<Vec<String>>,
+    pub cache_to: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SecurityOptions {
+    // in bytes
+    pub shm_size: Option<i64>,
+    pub pids_limit: Option<i64>,
+    // in bytes
+    pub memory_swap: Option<i64>,
+    #[serde(default)]
+    pub ulimits: Vec<Ulimit>,
+    pub cgroupns_mode: Option<String>,
+    pub userns_mode: Option<String>,
+    // e.g. "seccomp=unconfined", "no-new-privileges"
+    #[serde(default)]
+    pub security_opt: Vec<String>,
+    #[serde(default)]
+    pub read_only: bool,
+    // mount points to back with tmpfs when read_only is set
+    #[serde(default)]
+    pub tmpfs: Vec<PathBuf>,
+    // rendered as "host:ip" entries in HostConfig::extra_hosts
+    #[serde_as(as = "HashMap<_, DisplayFromStr>")]
+    #[serde(default)]
+    pub extra_hosts: HashMap<String, std::net::IpAddr>,
+}
+
+fn default_probe_timeout_secs() -> u64 {
+    30
+}
+
+fn default_probe_interval_ms() -> u64 {
+    500
+}
+
+fn default_probe_path() -> String {
+    "/".to_owned()
+}
+
+fn default_accepted_statuses() -> Vec<u16> {
+    vec![200]
+}
+
+// borrowed from the testcontainers wait-strategy model
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase", tag = "kind")]
+pub enum ReadinessProbe {
+    /// repeatedly attempt to connect to the mapped container port until success
+    Tcp {
+        port: u16,
+        #[serde(default = "default_probe_timeout_secs")]
+        timeout_secs: u64,
+        #[serde(default = "default_probe_interval_ms")]
+        interval_ms: u64,
+    },
+    /// GET a path on the exposed port and wait for an accepted status
+    Http {
+        port: u16,
+        #[serde(default = "default_probe_path")]
+        path: String,
+        #[serde(default = "default_accepted_statuses")]
+        accepted_statuses: Vec<u16>,
+        #[serde(default = "default_probe_timeout_secs")]
+        timeout_secs: u64,
+        #[serde(default = "default_probe_interval_ms")]
+        interval_ms: u64,
+    },
+    /// tail the container's stdout/stderr until a regex matches
+    Log {
+        regex: String,
+        #[serde(default = "default_probe_timeout_secs")]
+        timeout_secs: u64,
+    },
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
@@ -206,12 +417,20 @@ pub struct DeployableChallenge {
 pub struct ExperimentalOptions {
     #[serde(default)]
     pub use_docker_buildkit: bool,
+    // context-wide BuildKit registry cache refs used by any container that doesn't set its own
+    // `Container::cache_from`/`cache_to`. Only used when `use_docker_buildkit` is enabled.
+    #[serde(default)]
+    pub cache_from: Vec<String>,
+    #[serde(default)]
+    pub cache_to: Vec<String>,
 }
 
 impl Default for ExperimentalOptions {
     fn default() -> Self {
         Self {
             use_docker_buildkit: false,
+            cache_from: Vec::new(),
+            cache_to: Vec::new(),
         }
     }
 }
@@ -222,6 +441,10 @@ pub struct DeployableContextData {
     // TODO(aiden): rename to daemon
     pub docker: DockerData,
     pub docker_credentials: Option<bollard::auth::DockerCredentials>,
+    // known registries a `Container::registry` override can select by `serveraddress` alone,
+    // without repeating the username/password/identitytoken in every challenge's chall.toml
+    #[serde(default)]
+    pub registry_credentials: Vec<bollard::auth::DockerCredentials>,
     // TODO(aiden): image_prefix and repo are basically the same thing iirc? get rid of image_prefix
     pub image_prefix: String,
     pub repo: String,
@@ -289,6 +512,7 @@ impl TryInto<DeployableContext> for DeployableContextData {
         Ok(DeployableContext {
             docker: self.docker.try_into()?,
             docker_credentials: self.docker_credentials,
+            registry_credentials: self.registry_credentials,
             image_prefix: self.image_prefix,
             repo: self.repo,
             experimental: self.experimental,
@@ -301,6 +525,7 @@ impl TryInto<DeployableContext> for DeployableContextData {
 pub struct DeployableContext {
     pub docker: bollard::Docker,
     pub docker_credentials: Option<bollard::auth::DockerCredentials>,
+    pub registry_credentials: Vec<bollard::auth::DockerCredentials>,
     pub image_prefix: String,
     pub repo: String,
     pub experimental: ExperimentalOptions,
@@ -311,6 +536,154 @@ pub fn is_valid_id(id: &str) -> bool {
         .all(|c| (!c.is_uppercase() && c.is_alphanumeric()) || c == '-')
 }
 
+/// Loads `.dockerignore` patterns from a build context directory, if present. Each non-blank,
+/// non-comment line is converted to a regex anchored to the whole relative path so matching
+/// stays simple (no full dockerignore glob semantics like `**` or `!`-negation, just the
+/// subset we need: literal segments and `*`/`?` wildcards).
+fn load_dockerignore_patterns(context_dir: &Path) -> Result<Vec<Regex>> {
+    let dockerignore_path = context_dir.join(".dockerignore");
+    if !dockerignore_path.exists() {
+        return Ok(vec![]);
+    }
+
+    let contents = fs::read_to_string(&dockerignore_path)
+        .with_context(|| format!("Failed to read {}", dockerignore_path.display()))?;
+
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|pattern| {
+            let pattern = pattern.trim_end_matches('/');
+            let mut regex_str = String::from("^");
+            for ch in pattern.chars() {
+                match ch {
+                    '*' => regex_str.push_str(".*"),
+                    '?' => regex_str.push('.'),
+                    _ => regex_str.push_str(&regex::escape(&ch.to_string())),
+                }
+            }
+            regex_str.push_str(r"(/.*)?$");
+            Regex::new(&regex_str).with_context(|| format!("Invalid .dockerignore pattern {pattern}"))
+        })
+        .collect()
+}
+
+fn is_ignored(rel_path: &Path, patterns: &[Regex]) -> bool {
+    let rel_str = rel_path.to_string_lossy().replace('\\', "/");
+    patterns.iter().any(|re| re.is_match(&rel_str))
+}
+
+/// Builds matchers for `Attachment::Archive`'s `exclude` list: an entry ending in `/` matches
+/// that directory and everything under it, an entry containing `*` is a glob wildcard, anything
+/// else must match the relative path exactly.
+fn exclude_patterns(exclude: &[PathBuf]) -> Vec<Regex> {
+    exclude
+        .iter()
+        .map(|entry| {
+            let raw = entry.to_string_lossy().replace('\\', "/");
+            let is_dir_prefix = raw.ends_with('/');
+            let trimmed = raw.trim_end_matches('/');
+
+            let mut regex_str = String::from("^");
+            for ch in trimmed.chars() {
+                match ch {
+                    '*' => regex_str.push_str(".*"),
+                    _ => regex_str.push_str(&regex::escape(&ch.to_string())),
+                }
+            }
+            regex_str.push_str(if is_dir_prefix { r"(/.*)?$" } else { "$" });
+
+            Regex::new(&regex_str).expect("built from an escaped literal plus `.*`, always valid")
+        })
+        .collect()
+}
+
+/// Packs `context_dir` into a gzipped tar written to `out_path`, honoring `.dockerignore` if
+/// present, so the resulting context is a single self-contained stream that can be fed to
+/// `bollard`'s image-build API regardless of whether the daemon is local or remote - the
+/// daemon never needs its own filesystem access to the challenge directory. Symlinks are
+/// preserved as symlinks rather than dereferenced, and files are streamed into the archive
+/// rather than buffered in memory, so large build contexts don't blow up memory usage.
+fn pack_build_context(context_dir: &Path, out_path: &Path) -> Result<()> {
+    let patterns = load_dockerignore_patterns(context_dir)?;
+
+    let tar_file = StdFile::create(out_path)?;
+    let enc = GzEncoder::new(tar_file, Compression::default());
+    let mut tar_ = tar::Builder::new(enc);
+
+    for entry in WalkDir::new(context_dir).min_depth(1).into_iter() {
+        let entry = entry.with_context(|| format!("Failed to walk {}", context_dir.display()))?;
+        let rel_path = entry.path().strip_prefix(context_dir)?;
+
+        if is_ignored(rel_path, &patterns) {
+            continue;
+        }
+
+        let file_type = entry.file_type();
+        if file_type.is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            tar_.append_link(&mut header, rel_path, &target)
+                .with_context(|| format!("Failed to add symlink {}", entry.path().display()))?;
+        } else if file_type.is_dir() {
+            tar_.append_dir(rel_path, entry.path())
+                .with_context(|| format!("Failed to add directory {}", entry.path().display()))?;
+        } else {
+            tar_.append_path_with_name(entry.path(), rel_path)
+                .with_context(|| format!("Failed to add file {}", entry.path().display()))?;
+        }
+    }
+
+    tar_.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Appends `src` (file, directory, or symlink) to `tar_` as `archive_path`, forcing `mtime`,
+/// `uid`/`gid`, and `mode` to fixed values so an unchanged directory always produces a
+/// byte-identical tar - `tar::Builder`'s own convenience methods (`append_dir_all`, etc.) embed
+/// the host's mtime, which would otherwise make the uploaded archive's content hash churn on
+/// every build even when nothing actually changed.
+fn append_reproducible<W: Write>(
+    tar_: &mut tar::Builder<W>,
+    src: &Path,
+    archive_path: &Path,
+    file_type: fs::FileType,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+
+    if file_type.is_symlink() {
+        let target = fs::read_link(src)?;
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_cksum();
+        tar_.append_link(&mut header, archive_path, &target)
+            .with_context(|| format!("Failed to add symlink {}", src.display()))?;
+    } else if file_type.is_dir() {
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_cksum();
+        tar_.append_data(&mut header, archive_path, std::io::empty())
+            .with_context(|| format!("Failed to add directory {}", src.display()))?;
+    } else {
+        let data = fs::read(src)?;
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar_.append_data(&mut header, archive_path, data.as_slice())
+            .with_context(|| format!("Failed to add file {}", src.display()))?;
+    }
+
+    Ok(())
+}
+
 impl DeployableChallenge {
     pub fn from_root(root: PathBuf) -> Result<Self> {
         let chall_data = fs::read_to_string(root.join("challenge.toml"))
@@ -324,11 +697,18 @@ impl DeployableChallenge {
         Ok(Self { chall, root })
     }
 
-    pub async fn build_ct(
+    /// Packs `ct`'s build context and kicks off its image build, returning the raw bollard
+    /// build stream as it arrives rather than buffering it - so a caller (e.g. an SSE handler)
+    /// can forward each step, including intermediate layer progress and error frames, to a
+    /// client as soon as it's produced instead of waiting for the whole image to finish.
+    /// Returns `None` if `ct` isn't a configured container. The packed context's `TempDir` is
+    /// kept alive for as long as the returned stream is, since its tarball is read lazily as
+    /// the build request body streams out.
+    pub async fn build_ct_stream(
         &self,
         ctx: &DeployableContext,
         ct: &str,
-    ) -> Result<Option<Vec<bollard::models::BuildInfo>>> {
+    ) -> Result<Option<impl Stream<Item = Result<bollard::models::BuildInfo>>>> {
         let Some(chall_containers) = &self.chall.container else {
             return Ok(None);
         };
@@ -338,24 +718,17 @@ impl DeployableChallenge {
         };
 
         let tmp = TempDir::new(&self.chall.id)?;
-        let context_tar_path = tmp.path().join("docker.tar");
-        {
-            // ugh
-            let tar_file = StdFile::create(&context_tar_path)?;
-            let mut tar_ = tar::Builder::new(tar_file);
-            tar_.sparse(false);
-            let context_dir_path = self.root.join(&chall_container.build);
-            tar_.append_dir_all(".", &context_dir_path)
-                .with_context(|| {
-                    format!(
-                        "Failed to read Docker context {}",
-                        context_dir_path.display()
-                    )
-                })?;
-            tar_.finish()?;
-        }
+        let context_tar_path = tmp.path().join("docker.tar.gz");
+        let context_dir_path = self.root.join(&chall_container.build);
+        pack_build_context(&context_dir_path, &context_tar_path).with_context(|| {
+            format!(
+                "Failed to pack Docker context {}",
+                context_dir_path.display()
+            )
+        })?;
 
         let session_id = format!("{}-{}", self.chall.id, ct);
+        let (cache_from, cache_to) = self.chall.build_cache_refs(ctx, ct);
 
         let options = bollard::query_parameters::BuildImageOptionsBuilder::new()
             // FIXME(ani): idk if it's ideal to tag the image with the repo name in build
@@ -368,20 +741,44 @@ impl DeployableChallenge {
                 bollard::query_parameters::BuilderVersion::BuilderV1
             })
             .session(&session_id)
+            .cachefrom(cache_from)
+            .cacheto(cache_to)
             .build();
 
         let tar_file_r = File::open(&context_tar_path).await?;
         let tar_file_r = ReaderStream::new(tar_file_r);
-        let mut build =
-            ctx.docker
-                .build_image(options, None, Some(bollard::body_try_stream(tar_file_r)));
+        let build = ctx
+            .docker
+            .build_image(options, None, Some(bollard::body_try_stream(tar_file_r)));
+
+        Ok(Some(futures_util::stream::unfold(
+            (build, tmp),
+            |(mut build, tmp)| async move {
+                let step = build.next().await?;
+                let step = step.context("Docker build image error").map(|info| {
+                    if let Some(stream) = &info.stream {
+                        info!("{}", stream);
+                    }
+                    info
+                });
+                Some((step, (build, tmp)))
+            },
+        )))
+    }
+
+    pub async fn build_ct(
+        &self,
+        ctx: &DeployableContext,
+        ct: &str,
+    ) -> Result<Option<Vec<bollard::models::BuildInfo>>> {
+        let Some(stream) = self.build_ct_stream(ctx, ct).await? else {
+            return Ok(None);
+        };
+
+        tokio::pin!(stream);
         let mut build_infos = vec![];
-        while let Some(build_step) = build.next().await {
-            let build_step = build_step.context("Docker build image error")?;
-            if let Some(stream) = &build_step.stream {
-                info!("{}", stream);
-            }
-            build_infos.push(build_step);
+        while let Some(build_step) = stream.next().await {
+            build_infos.push(build_step?);
         }
 
         Ok(Some(build_infos))
@@ -413,8 +810,123 @@ impl DeployableChallenge {
         self.chall.push(ctx).await
     }
 
+    /// Starts `verify.container`'s already-built image, execs the solve command against it, and
+    /// asserts the real flag value shows up in the captured output, so CI can catch a challenge
+    /// that doesn't actually yield its own flag before it ships. Returns `None` if the challenge
+    /// has no `verify` section configured. The throwaway container is always removed afterwards,
+    /// whether verification passed, failed, or timed out.
+    pub async fn verify(&self, ctx: &DeployableContext) -> Result<Option<VerifyReport>> {
+        let Some(verify) = &self.chall.verify else {
+            return Ok(None);
+        };
+
+        let create_options = CreateContainerOptionsBuilder::new()
+            .name(&format!("{}-verify", self.chall.id))
+            .build();
+        let container = ctx
+            .docker
+            .create_container(
+                Some(create_options),
+                ContainerCreateBody {
+                    image: Some(self.chall.image_id(ctx, &verify.container)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .context("Failed to create throwaway container for solver verification")?;
+
+        let result = self.run_verify(ctx, &container.id, verify).await;
+
+        let remove_options = RemoveContainerOptionsBuilder::new().force(true).build();
+        if let Err(e) = ctx
+            .docker
+            .remove_container(&container.id, Some(remove_options))
+            .await
+        {
+            log::warn!(
+                "Failed to remove throwaway verification container {}: {:?}",
+                container.id,
+                e
+            );
+        }
+
+        Ok(Some(result?))
+    }
+
+    async fn run_verify(
+        &self,
+        ctx: &DeployableContext,
+        container_id: &str,
+        verify: &Verify,
+    ) -> Result<VerifyReport> {
+        ctx.docker
+            .start_container(container_id, None::<StartContainerOptions>)
+            .await
+            .context("Failed to start throwaway container for solver verification")?;
+
+        let flag = self.chall.flag.resolve(&self.root)?;
+        let started = Instant::now();
+
+        let run_exec = async {
+            let exec = ctx
+                .docker
+                .create_exec(
+                    container_id,
+                    CreateExecOptions {
+                        cmd: Some(verify.cmd.clone()),
+                        attach_stdout: Some(true),
+                        attach_stderr: Some(true),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            let mut stdout = String::new();
+            let mut stderr = String::new();
+
+            // bollard demultiplexes Docker's `[stream_type, 0,0,0, size_be, payload]` exec
+            // frames for us already, yielding one `LogOutput` per frame
+            if let bollard::exec::StartExecResults::Attached { mut output, .. } = ctx
+                .docker
+                .start_exec(&exec.id, Some(StartExecOptions::default()))
+                .await?
+            {
+                use bollard::container::LogOutput;
+                while let Some(chunk) = output.next().await {
+                    match chunk? {
+                        LogOutput::StdOut { message } => {
+                            stdout.push_str(&String::from_utf8_lossy(&message))
+                        }
+                        LogOutput::StdErr { message } => {
+                            stderr.push_str(&String::from_utf8_lossy(&message))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            Ok::<_, bollard::errors::Error>((stdout, stderr))
+        };
+
+        let (stdout, stderr) = tokio::time::timeout(
+            std::time::Duration::from_secs(verify.timeout_secs),
+            run_exec,
+        )
+        .await
+        .map_err(|_| eyre!("Solver verification timed out after {}s", verify.timeout_secs))?
+        .context("Docker exec error during solver verification")?;
+
+        Ok(VerifyReport {
+            passed: stdout.contains(&flag) || stderr.contains(&flag),
+            stdout,
+            stderr,
+            duration_ms: started.elapsed().as_millis(),
+        })
+    }
+
     pub async fn push_attachments(
         &self,
+        ctx: &DeployableContext,
         uploader: &Uploader,
     ) -> Result<HashMap<String, String>> {
         if self.chall.provide.is_none() {
@@ -436,16 +948,33 @@ impl DeployableChallenge {
                 Attachment::Archive { dir, r#as, exclude } => {
                     let tmp = TempDir::new(&self.chall.id)?;
                     let tar_path = tmp.path().join("chall.tar.gz");
+                    let dir_path = self.root.join(dir);
+                    let patterns = exclude.as_deref().map(exclude_patterns).unwrap_or_default();
 
                     // ugh
                     {
                         let tar_file = StdFile::create(&tar_path)?;
                         let enc = GzEncoder::new(tar_file, Compression::default());
                         let mut tar_ = tar::Builder::new(enc);
-                        // TODO actually support exclude
-                        tar_.append_dir_all(r#as, self.root.join(dir))?;
 
-                        tar_.finish()?;
+                        for entry in WalkDir::new(&dir_path)
+                            .sort_by_file_name()
+                            .min_depth(1)
+                            .into_iter()
+                        {
+                            let entry = entry
+                                .with_context(|| format!("Failed to walk {}", dir_path.display()))?;
+                            let rel_path = entry.path().strip_prefix(&dir_path)?;
+
+                            if is_ignored(rel_path, &patterns) {
+                                continue;
+                            }
+
+                            let archive_path = Path::new(r#as).join(rel_path);
+                            append_reproducible(&mut tar_, entry.path(), &archive_path, entry.file_type())?;
+                        }
+
+                        tar_.into_inner()?.finish()?;
                     }
 
                     let mut buffer = Vec::new();
@@ -453,11 +982,194 @@ impl DeployableChallenge {
 
                     (format!("{as}.tar.gz"), buffer)
                 }
+                Attachment::FromContainer { container, path, r#as } => {
+                    self.extract_from_container(ctx, container, path, r#as).await?
+                }
             };
 
-            let url_for_download = uploader.upload(&self.chall.id, &name, data).await?;
-            hm.insert(name, url_for_download);
+            // content-addressed: identical bytes shared across challenges (or re-pushed on an
+            // unchanged run) upload once, keyed by digest rather than `<chall_id>/<filename>` -
+            // the original filename is carried by the Content-Disposition override on signed GCS/S3
+            // URLs; the platform-proxy and file:// stores ignore it
+            let options = uploader.default_options().with_filename(&name);
+            let hashed = uploader.upload_hashed(data, options).await?;
+            hm.insert(name, hashed.url);
         }
         return Ok(hm);
     }
+
+    /// Creates a throwaway (unstarted) container from the image already built for `ct`, copies
+    /// `path` out of it via the Docker "copy from container" endpoint, and deletes the container
+    /// again regardless of whether the copy succeeded.
+    async fn extract_from_container(
+        &self,
+        ctx: &DeployableContext,
+        ct: &str,
+        path: &Path,
+        r#as: &str,
+    ) -> Result<(String, Vec<u8>)> {
+        let create_options = CreateContainerOptionsBuilder::new()
+            .name(&format!("{}-attachment-extract", self.chall.id))
+            .build();
+        let container = ctx
+            .docker
+            .create_container(
+                Some(create_options),
+                ContainerCreateBody {
+                    image: Some(self.chall.image_id(ctx, ct)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .context("Failed to create throwaway container for attachment extraction")?;
+
+        let result = self.copy_from_container(ctx, &container.id, path, r#as).await;
+
+        let remove_options = RemoveContainerOptionsBuilder::new().force(true).build();
+        if let Err(e) = ctx
+            .docker
+            .remove_container(&container.id, Some(remove_options))
+            .await
+        {
+            log::warn!(
+                "Failed to remove throwaway attachment-extraction container {}: {:?}",
+                container.id,
+                e
+            );
+        }
+
+        result
+    }
+
+    async fn copy_from_container(
+        &self,
+        ctx: &DeployableContext,
+        container_id: &str,
+        path: &Path,
+        r#as: &str,
+    ) -> Result<(String, Vec<u8>)> {
+        let download_options = DownloadFromContainerOptionsBuilder::new()
+            .path(&path.to_string_lossy())
+            .build();
+
+        let mut stream = ctx
+            .docker
+            .download_from_container(container_id, Some(download_options));
+
+        let mut tar_bytes = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            tar_bytes.extend_from_slice(
+                &chunk.context("Failed to stream attachment archive from container")?,
+            );
+        }
+
+        // the archive endpoint always returns a tar (even for a single file), wrapping every
+        // entry in one leading path component named after `path`'s basename - strip it to get
+        // paths relative to `path` itself
+        let mut archive = tar::Archive::new(tar_bytes.as_slice());
+        let mut entries: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_dir() {
+                continue;
+            }
+
+            let entry_path = entry.path()?.into_owned();
+            let rel_path: PathBuf = entry_path.components().skip(1).collect();
+
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            entries.push((rel_path, data));
+        }
+
+        if entries.len() == 1 && entries[0].0.as_os_str().is_empty() {
+            // `path` pointed at a single file - the only entry is that file itself
+            return Ok((r#as.to_string(), entries.remove(0).1));
+        }
+
+        // `path` pointed at a directory - re-gzip it the way the `Archive` variant does
+        let mut buffer = Vec::new();
+        {
+            let enc = GzEncoder::new(&mut buffer, Compression::default());
+            let mut tar_ = tar::Builder::new(enc);
+            for (rel_path, data) in &entries {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                tar_.append_data(&mut header, Path::new(r#as).join(rel_path), data.as_slice())?;
+            }
+            tar_.into_inner()?.finish()?;
+        }
+
+        Ok((format!("{as}.tar.gz"), buffer))
+    }
+}
+
+#[cfg(test)]
+mod build_context_tests {
+    use super::{StdFile, pack_build_context};
+    use flate2::read::GzDecoder;
+    use std::{fs, io::Read, path::PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    fn excludes_dockerignored_paths_and_preserves_the_rest() {
+        let src = TempDir::new("pack-src").unwrap();
+        fs::write(src.path().join("Dockerfile"), "FROM scratch").unwrap();
+        fs::create_dir(src.path().join("target")).unwrap();
+        fs::write(src.path().join("target/build-output.bin"), "junk").unwrap();
+        fs::write(src.path().join(".dockerignore"), "target\n").unwrap();
+
+        let out = TempDir::new("pack-out").unwrap();
+        let out_path = out.path().join("context.tar.gz");
+        pack_build_context(src.path(), &out_path).unwrap();
+
+        let entries = list_entries(&out_path);
+        assert!(entries.contains(&"Dockerfile".to_string()));
+        assert!(!entries.iter().any(|e| e.starts_with("target")));
+    }
+
+    #[test]
+    fn preserves_symlinks_and_streams_large_files() {
+        let src = TempDir::new("pack-src").unwrap();
+        let large_contents = vec![b'x'; 5 * 1024 * 1024];
+        fs::write(src.path().join("large.bin"), &large_contents).unwrap();
+        std::os::unix::fs::symlink("large.bin", src.path().join("link-to-large.bin")).unwrap();
+
+        let out = TempDir::new("pack-out").unwrap();
+        let out_path = out.path().join("context.tar.gz");
+        pack_build_context(src.path(), &out_path).unwrap();
+
+        let tar_file = StdFile::open(&out_path).unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(tar_file));
+        let mut found_symlink = false;
+        let mut found_large_file = false;
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_path_buf();
+            if path == PathBuf::from("link-to-large.bin") {
+                assert_eq!(entry.header().entry_type(), tar::EntryType::Symlink);
+                assert_eq!(entry.link_name().unwrap().unwrap(), PathBuf::from("large.bin"));
+                found_symlink = true;
+            } else if path == PathBuf::from("large.bin") {
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents).unwrap();
+                assert_eq!(contents, large_contents);
+                found_large_file = true;
+            }
+        }
+        assert!(found_symlink);
+        assert!(found_large_file);
+    }
+
+    fn list_entries(tar_gz_path: &std::path::Path) -> Vec<String> {
+        let tar_file = StdFile::open(tar_gz_path).unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(tar_file));
+        archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
 }
@@ -1,149 +1,667 @@
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    config::{Credentials, Region},
+    presigning::PresigningConfig,
+    types::{CompletedMultipartUpload, CompletedPart},
+    Client as S3Client,
+};
+use bytes::Bytes;
 use eyre::Result;
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use google_cloud_storage::{
     client::{Client as GcsClient, ClientConfig},
     http::objects::upload::{Media, UploadObjectRequest, UploadType},
+    http::resumable_upload_client::{ChunkSize, ResumableUploadClient, UploadStatus},
     sign::SignedURLOptions,
 };
-use std::time::Duration;
-use std::{env, sync::Arc};
 use reqwest::{cookie::Jar, multipart, Url};
+use std::{collections::HashMap, path::PathBuf, pin::Pin, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
 use tokio_util::codec::{BytesCodec, FramedRead};
-use futures_util::TryStreamExt;
 
-enum UploaderBackend {
-    Gcs(google_cloud_storage::client::Client),
-    // S3
-    Local {
-        platform_base: String,
-        admin_token: String,
+/// Boxed byte stream shared by every `ObjectStore` impl, so `put`/`get` are dyn-compatible
+/// regardless of where the bytes actually come from (an in-memory buffer, a file on disk, an
+/// HTTP response body).
+pub type ByteStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+/// Uploads at or below this size go through a single `PutObject`; larger ones are split into
+/// parts so we never have to hand the whole buffer to one request.
+const S3_MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// GCS resumable uploads are driven one chunk at a time at this size, so a large attachment
+/// never has to be fully resident in memory.
+const GCS_RESUMABLE_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+const GCS_CHUNK_MAX_RETRIES: u32 = 3;
+
+/// Per-upload knobs that used to be hardcoded: how long a signed URL stays valid, what headers
+/// the download response should carry, and whether the object should be handed back as a plain
+/// public URL instead of a signed one.
+#[derive(Clone, Debug)]
+pub struct UploadOptions {
+    pub expiry: Duration,
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+    pub public: bool,
+}
+
+impl UploadOptions {
+    pub fn new(default_expiry: Duration) -> Self {
+        Self {
+            expiry: default_expiry,
+            content_type: None,
+            content_disposition: None,
+            public: false,
+        }
+    }
+
+    /// Forces the response to download under `filename` instead of whatever the browser decides.
+    pub fn with_filename(mut self, filename: &str) -> Self {
+        self.content_disposition = Some(format!("attachment; filename=\"{filename}\""));
+        self
+    }
+
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+}
+
+/// One object-storage backend, addressed by a scheme like `gs://bucket`, `s3://bucket`, or
+/// `file:///path`. Mirrors the object_store/OpenDAL scheme-based design: a challenge upload is
+/// just `store.put(key, stream, ...)` regardless of which backend is actually configured, which
+/// also makes it trivial to point tests at an in-memory store.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn put(&self, key: &str, stream: ByteStream, len_hint: Option<u64>, content_type: Option<&str>) -> Result<()>;
+    async fn get(&self, key: &str) -> Result<ByteStream>;
+    async fn signed_url(&self, key: &str, options: &UploadOptions) -> Result<String>;
+    async fn delete(&self, key: &str) -> Result<()>;
+    /// Whether an object already sits at `key`, so content-addressed uploads can skip a `put`
+    /// entirely when the bytes are already stored.
+    async fn exists(&self, key: &str) -> Result<bool>;
+}
+
+/// Uploads one GCS resumable-upload chunk, retrying up to `GCS_CHUNK_MAX_RETRIES` times with a
+/// linear backoff on transient failures before giving up.
+async fn upload_gcs_chunk_with_retry(
+    upload_client: &ResumableUploadClient,
+    chunk: Vec<u8>,
+    offset: u64,
+    len_hint: Option<u64>,
+) -> Result<UploadStatus> {
+    let size = ChunkSize::new(offset, offset + chunk.len() as u64 - 1, len_hint);
+
+    let mut attempt = 0;
+    loop {
+        match upload_client.upload_multiple_chunk(chunk.clone(), &size).await {
+            Ok(status) => return Ok(status),
+            Err(e) if attempt < GCS_CHUNK_MAX_RETRIES => {
+                attempt += 1;
+                log::warn!("GCS chunk upload failed (attempt {attempt}/{GCS_CHUNK_MAX_RETRIES}): {e}");
+                tokio::time::sleep(Duration::from_millis(500 * attempt as u64)).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
     }
 }
 
+pub struct GcsStore {
+    client: GcsClient,
+    bucket: String,
+}
+
+#[async_trait]
+impl ObjectStore for GcsStore {
+    async fn put(&self, key: &str, mut stream: ByteStream, len_hint: Option<u64>, content_type: Option<&str>) -> Result<()> {
+        let mut media = Media::new(key.to_owned());
+        if let Some(content_type) = content_type {
+            media.content_type = content_type.to_owned().into();
+        }
+
+        let upload_client = self
+            .client
+            .prepare_resumable_upload(
+                &UploadObjectRequest {
+                    bucket: self.bucket.clone(),
+                    ..Default::default()
+                },
+                &UploadType::Multipart(Box::new(media)),
+            )
+            .await?;
+
+        let mut buffer: Vec<u8> = Vec::with_capacity(GCS_RESUMABLE_CHUNK_SIZE);
+        let mut sent: u64 = 0;
+
+        while let Some(chunk) = stream.next().await {
+            buffer.extend_from_slice(&chunk?);
+
+            while buffer.len() >= GCS_RESUMABLE_CHUNK_SIZE {
+                let part: Vec<u8> = buffer.drain(..GCS_RESUMABLE_CHUNK_SIZE).collect();
+                let part_len = part.len() as u64;
+                upload_gcs_chunk_with_retry(&upload_client, part, sent, len_hint).await?;
+                sent += part_len;
+            }
+        }
+
+        // flush whatever's left as the final chunk, even an empty file
+        if !buffer.is_empty() || sent == 0 {
+            let remaining = buffer.len() as u64;
+            upload_gcs_chunk_with_retry(&upload_client, buffer, sent, Some(sent + remaining)).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream> {
+        Err(eyre::eyre!("get is not implemented for the GCS object store ({key})"))
+    }
+
+    async fn signed_url(&self, key: &str, options: &UploadOptions) -> Result<String> {
+        if options.public {
+            return Ok(format!("https://storage.googleapis.com/{}/{}", self.bucket, key));
+        }
+
+        let mut query_parameters = HashMap::new();
+        if let Some(content_type) = &options.content_type {
+            query_parameters.insert("response-content-type".to_owned(), content_type.clone());
+        }
+        if let Some(content_disposition) = &options.content_disposition {
+            query_parameters.insert("response-content-disposition".to_owned(), content_disposition.clone());
+        }
+
+        Ok(self
+            .client
+            .signed_url(
+                &self.bucket,
+                key,
+                None,
+                None,
+                SignedURLOptions {
+                    expires: options.expiry,
+                    query_parameters,
+                    ..Default::default()
+                },
+            )
+            .await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        Err(eyre::eyre!("delete is not implemented for the GCS object store ({key})"))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        use google_cloud_storage::http::objects::get::GetObjectRequest;
+
+        match self
+            .client
+            .get_object(&GetObjectRequest {
+                bucket: self.bucket.clone(),
+                object: key.to_owned(),
+                ..Default::default()
+            })
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                log::debug!("GCS existence check for {key} treated as a miss: {e}");
+                Ok(false)
+            }
+        }
+    }
+}
+
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+    // when set, attachments are handed back by this public base URL instead of a presigned one
+    public_base: Option<String>,
+}
+
+impl S3Store {
+    /// Uploads `data` as a multipart object in `S3_MULTIPART_PART_SIZE`-sized chunks so the
+    /// whole buffer never has to go out over the wire in one request.
+    async fn multipart_put(&self, key: &str, data: Vec<u8>, content_type: Option<&str>) -> Result<()> {
+        let mut create = self.client.create_multipart_upload().bucket(&self.bucket).key(key);
+        if let Some(content_type) = content_type {
+            create = create.content_type(content_type);
+        }
+        let create = create.send().await?;
+        let upload_id = create
+            .upload_id()
+            .ok_or_else(|| eyre::eyre!("S3 did not return an upload id"))?;
+
+        let mut completed_parts = Vec::new();
+        for (i, chunk) in data.chunks(S3_MULTIPART_PART_SIZE).enumerate() {
+            let part_number = i as i32 + 1;
+            let uploaded = self
+                .client
+                .upload_part()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(chunk.to_vec().into())
+                .send()
+                .await?;
+
+            completed_parts.push(
+                CompletedPart::builder()
+                    .part_number(part_number)
+                    .set_e_tag(uploaded.e_tag().map(str::to_owned))
+                    .build(),
+            );
+        }
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(completed_parts))
+                    .build(),
+            )
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3Store {
+    async fn put(&self, key: &str, mut stream: ByteStream, _len_hint: Option<u64>, content_type: Option<&str>) -> Result<()> {
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+
+        if data.len() > S3_MULTIPART_PART_SIZE {
+            self.multipart_put(key, data, content_type).await
+        } else {
+            let mut put = self.client.put_object().bucket(&self.bucket).key(key).body(data.into());
+            if let Some(content_type) = content_type {
+                put = put.content_type(content_type);
+            }
+            put.send().await?;
+            Ok(())
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await?;
+
+        let stream = object.body.map(|chunk| chunk.map_err(std::io::Error::other));
+        Ok(Box::pin(stream))
+    }
+
+    async fn signed_url(&self, key: &str, options: &UploadOptions) -> Result<String> {
+        if options.public {
+            if let Some(public_base) = &self.public_base {
+                return Ok(format!("{}/{}", public_base.trim_end_matches('/'), key));
+            }
+        }
+
+        let mut req = self.client.get_object().bucket(&self.bucket).key(key);
+        if let Some(content_type) = &options.content_type {
+            req = req.response_content_type(content_type);
+        }
+        if let Some(content_disposition) = &options.content_disposition {
+            req = req.response_content_disposition(content_disposition);
+        }
+
+        let presigned = req.presigned(PresigningConfig::expires_in(options.expiry)?).await?;
+
+        Ok(presigned.uri().to_string())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.client.delete_object().bucket(&self.bucket).key(key).send().await?;
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self.client.head_object().bucket(&self.bucket).key(key).send().await {
+            Ok(_) => Ok(true),
+            Err(aws_sdk_s3::error::SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Stores objects directly on the local filesystem under `base`, for the `file://` scheme.
+pub struct LocalFileStore {
+    base: PathBuf,
+}
+
+#[async_trait]
+impl ObjectStore for LocalFileStore {
+    async fn put(&self, key: &str, mut stream: ByteStream, _len_hint: Option<u64>, _content_type: Option<&str>) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let path = self.base.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = tokio::fs::File::create(&path).await?;
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream> {
+        let file = tokio::fs::File::open(self.base.join(key)).await?;
+        let stream = tokio_util::io::ReaderStream::new(file);
+        Ok(Box::pin(stream))
+    }
+
+    async fn signed_url(&self, key: &str, _options: &UploadOptions) -> Result<String> {
+        Ok(format!("file://{}", self.base.join(key).display()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        tokio::fs::remove_file(self.base.join(key)).await?;
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(tokio::fs::metadata(self.base.join(key)).await.is_ok())
+    }
+}
+
+/// Legacy backend: proxies the upload through the platform's own admin attachment-upload API
+/// instead of writing to a bucket directly, for setups without S3/GCS credentials configured.
+/// The admin API hands back the final URL as part of the upload response rather than on demand,
+/// so `put` stashes it here for `signed_url` to return.
+pub struct PlatformStore {
+    platform_base: String,
+    admin_token: String,
+    urls: Mutex<HashMap<String, String>>,
+}
+
+impl PlatformStore {
+    fn admin_client(&self) -> Result<reqwest::Client> {
+        let jar = Jar::default();
+        jar.add_cookie_str(
+            &format!("admin_token={}", self.admin_token),
+            &Url::parse(&self.platform_base)?,
+        );
+
+        Ok(reqwest::Client::builder().cookie_provider(Arc::new(jar)).build()?)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for PlatformStore {
+    async fn put(&self, key: &str, mut stream: ByteStream, _len_hint: Option<u64>, content_type: Option<&str>) -> Result<()> {
+        let (chall_id, filename) = key
+            .split_once('/')
+            .ok_or_else(|| eyre::eyre!("expected key of the form <chall_id>/<filename>, got {key:?}"))?;
+
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+
+        let cursor = std::io::Cursor::new(data);
+        let body_stream = FramedRead::new(cursor, BytesCodec::new()).map_ok(|bytes| bytes.freeze());
+
+        // submit as multipart form with streaming file upload
+        let mut file_part = multipart::Part::stream(reqwest::Body::wrap_stream(body_stream))
+            .file_name(filename.to_string());
+        if let Some(content_type) = content_type {
+            file_part = file_part.mime_str(content_type)?;
+        }
+        let form = multipart::Form::new().part(filename.to_string(), file_part);
+
+        let response = self
+            .admin_client()?
+            .post(format!(
+                "{}/api/admin/attachments/upload?path={}",
+                self.platform_base, chall_id
+            ))
+            .multipart(form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(eyre::eyre!("Upload failed with status: {}", response.status()));
+        }
+
+        #[derive(serde::Deserialize)]
+        struct UploadResult {
+            url: String,
+        }
+
+        let results: Vec<UploadResult> = response.json().await?;
+        let url = results
+            .first()
+            .ok_or_else(|| eyre::eyre!("No upload result returned"))?
+            .url
+            .clone();
+
+        self.urls.lock().await.insert(key.to_owned(), url);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream> {
+        Err(eyre::eyre!("get is not supported for the platform-proxy object store ({key})"))
+    }
+
+    async fn signed_url(&self, key: &str, _options: &UploadOptions) -> Result<String> {
+        self.urls
+            .lock()
+            .await
+            .get(key)
+            .cloned()
+            .ok_or_else(|| eyre::eyre!("no URL recorded for {key:?} - put() must run first"))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        Err(eyre::eyre!("delete is not supported for the platform-proxy object store ({key})"))
+    }
+
+    async fn exists(&self, _key: &str) -> Result<bool> {
+        // the admin upload API has no stat/HEAD endpoint, so dedup is a no-op here - every call
+        // through this backend re-uploads
+        Ok(false)
+    }
+}
+
+/// Prefix for content-addressed objects, kept separate from the `<chall_id>/<filename>` keys
+/// `upload`/`upload_stream` use so a dedup'd object isn't shadowed by a per-challenge one.
+const CAS_PREFIX: &str = "cas/sha256";
+
+fn hex_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    })
+}
+
+/// Outcome of a content-addressed upload: where the object lives, and its digest for later
+/// integrity checks (e.g. verifying a downloaded attachment hasn't been tampered with).
+pub struct HashedUpload {
+    pub url: String,
+    pub sha256: String,
+}
+
 pub struct Uploader {
-    backend: UploaderBackend,
-    bucket: Option<String>,
+    store: Box<dyn ObjectStore>,
+    default_expiry: Duration,
 }
 
 impl Uploader {
     pub async fn from_env() -> Self {
-        let mut bucket = std::env::var("GCS_ATTACHMENTS_BUCKET").ok();
-        if bucket.is_none() {
-            if let Some(alt_bucket) = std::env::var("ATTACHMENTS_BUCKET").ok() {
-                bucket = Some(alt_bucket);
-            }
-        }
-        let backend = if std::env::var("GOOGLE_APPLICATION_CREDENTIALS_JSON").is_ok() {
-            // build GCS
-            UploaderBackend::Gcs(GcsClient::new(
-                        ClientConfig::default().with_auth().await.unwrap(),
-                    ))
+        let default_expiry = std::env::var("ATTACHMENT_PRESIGN_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(604800));
+
+        let store: Box<dyn ObjectStore> = if let Ok(destination) = std::env::var("ATTACHMENTS_DESTINATION") {
+            Self::store_from_uri(&destination)
+                .await
+                .expect("invalid ATTACHMENTS_DESTINATION")
+        } else if std::env::var("GOOGLE_APPLICATION_CREDENTIALS_JSON").is_ok() {
+            let bucket = std::env::var("GCS_ATTACHMENTS_BUCKET")
+                .or_else(|_| std::env::var("ATTACHMENTS_BUCKET"))
+                .expect("GCS_ATTACHMENTS_BUCKET must be set");
+            Box::new(GcsStore {
+                client: GcsClient::new(ClientConfig::default().with_auth().await.unwrap()),
+                bucket,
+            })
+        } else if let Ok(bucket) = std::env::var("S3_ATTACHMENTS_BUCKET") {
+            Box::new(Self::s3_store_from_env(bucket))
         } else {
-            UploaderBackend::Local {
+            Box::new(PlatformStore {
                 platform_base: std::env::var("PLATFORM_BASE").unwrap(),
                 admin_token: std::env::var("PLATFORM_ADMIN_TOKEN").unwrap(),
-            }
+                urls: Mutex::new(HashMap::new()),
+            })
         };
-        Self {
-            backend,
+
+        Self { store, default_expiry }
+    }
+
+    /// An [`UploadOptions`] pre-filled with the configured default expiry, ready for callers to
+    /// customize with `.with_filename(...)`/`.with_content_type(...)`/`.public()`.
+    pub fn default_options(&self) -> UploadOptions {
+        UploadOptions::new(self.default_expiry)
+    }
+
+    /// Parses a single destination URI (`gs://bucket`, `s3://bucket`, `file:///path`) into the
+    /// matching `ObjectStore`. For `gs://` and `s3://` only the bucket is taken from the URI; a path
+    /// after it is ignored and objects are keyed from the bucket root. This is the preferred way to
+    /// configure a destination, replacing the scattered env-var sniffing `from_env` otherwise falls
+    /// back to.
+    async fn store_from_uri(uri: &str) -> Result<Box<dyn ObjectStore>> {
+        let url = Url::parse(uri)?;
+
+        match url.scheme() {
+            "gs" => {
+                let bucket = url
+                    .host_str()
+                    .ok_or_else(|| eyre::eyre!("{uri} has no bucket"))?
+                    .to_owned();
+                Ok(Box::new(GcsStore {
+                    client: GcsClient::new(ClientConfig::default().with_auth().await?),
+                    bucket,
+                }))
+            }
+            "s3" => {
+                let bucket = url
+                    .host_str()
+                    .ok_or_else(|| eyre::eyre!("{uri} has no bucket"))?
+                    .to_owned();
+                Ok(Box::new(Self::s3_store_from_env(bucket)))
+            }
+            "file" => Ok(Box::new(LocalFileStore {
+                base: PathBuf::from(url.path()),
+            })),
+            other => Err(eyre::eyre!("unsupported attachment destination scheme {other:?}")),
+        }
+    }
+
+    fn s3_store_from_env(bucket: String) -> S3Store {
+        let region = std::env::var("S3_REGION")
+            .or_else(|_| std::env::var("AWS_REGION"))
+            .unwrap_or_else(|_| "auto".to_owned());
+        let access_key_id =
+            std::env::var("AWS_ACCESS_KEY_ID").expect("AWS_ACCESS_KEY_ID must be set for S3 uploads");
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+            .expect("AWS_SECRET_ACCESS_KEY must be set for S3 uploads");
+
+        let mut config_builder = aws_sdk_s3::Config::builder()
+            .region(Region::new(region))
+            .credentials_provider(Credentials::new(
+                access_key_id,
+                secret_access_key,
+                None,
+                None,
+                "nerine-uploader",
+            ))
+            .force_path_style(true)
+            .behavior_version_latest();
+
+        if let Ok(endpoint) = std::env::var("S3_ENDPOINT") {
+            config_builder = config_builder.endpoint_url(endpoint);
+        }
+
+        S3Store {
+            client: S3Client::from_conf(config_builder.build()),
             bucket,
+            public_base: std::env::var("S3_PUBLIC_BASE_URL").ok(),
         }
+    }
 
+    /// Thin wrapper over [`Self::upload_stream`] for callers that already have the whole file
+    /// in memory.
+    pub async fn upload(&self, chall_id: &str, filename: &str, data: Vec<u8>, options: UploadOptions) -> Result<String> {
+        let len_hint = data.len() as u64;
+        let stream = futures_util::stream::once(async move { Ok(Bytes::from(data)) });
+        self.upload_stream(chall_id, filename, stream, Some(len_hint), options).await
     }
 
-    pub fn get_admin_client(&self) -> Result<reqwest::Client> {
-        if let UploaderBackend::Local { platform_base, admin_token } = &self.backend {
-            let jar = Jar::default();
-            jar.add_cookie_str(
-                &format!("admin_token={}", admin_token),
-                &Url::parse(&platform_base)?,
-            );
-            let client = reqwest::Client::builder()
-                .cookie_provider(Arc::new(jar))
-                .build()?;
-            
-            Ok(client)
-        } else {
-            Err(eyre::eyre!("Cannot get admin client for non-local uploader"))
-        }
-    }
-
-    pub async fn upload(&self, chall_id: &str, filename: &str, data: Vec<u8>) -> Result<String> {
-        match &self.backend {
-            UploaderBackend::Gcs(gcs_client) => {
-                let bucket = self.bucket.as_ref()
-                    .ok_or_else(|| eyre::eyre!("No bucket configured for GCS upload"))?;
-                
-                let upload_type = UploadType::Simple(Media::new(format!("{}/{}", chall_id, filename)));
-
-                let uploaded = gcs_client
-                    .upload_object(
-                        &UploadObjectRequest {
-                            bucket: bucket.clone(),
-                            ..Default::default()
-                        },
-                        data,
-                        &upload_type,
-                    )
-                    .await?;
-
-                let url_for_download = gcs_client
-                    .signed_url(
-                        bucket,
-                        &uploaded.name,
-                        None,
-                        None,
-                        SignedURLOptions {
-                            expires: Duration::from_secs(604800),
-                            ..Default::default()
-                        },
-                    )
-                    .await?;
-
-                Ok(url_for_download)
-            },
-            UploaderBackend::Local {
-                platform_base,
-                admin_token,
-            } => {
-                let admin_client = self.get_admin_client()?;
-                
-                let cursor = std::io::Cursor::new(data);
-                let stream = FramedRead::new(cursor, BytesCodec::new())
-                    .map_ok(|bytes| bytes.freeze());
-                
-                // submit as multipart form with streaming file upload
-                let file_part = multipart::Part::stream(reqwest::Body::wrap_stream(stream))
-                    .file_name(filename.to_string());
-                
-                let form = multipart::Form::new()
-                    .part(filename.to_string(), file_part);
-                
-                // Upload via platform attahment upload API
-                let response = admin_client
-                    .post(format!("{}/api/admin/attachments/upload?path={}", platform_base, chall_id))
-                    .multipart(form)
-                    .send()
-                    .await?;
-                
-                if !response.status().is_success() {
-                    return Err(eyre::eyre!("Upload failed with status: {}", response.status()));
-                }
-                
-                #[derive(serde::Deserialize)]
-                struct UploadResult {
-                    url: String,
-                }
-
-                // let dbg = response.text().await?;
-                // println!("{dbg}");
-                
-                let results: Vec<UploadResult> = response.json().await?;
-                let url = results.first()
-                    .ok_or_else(|| eyre::eyre!("No upload result returned"))?
-                    .url.clone();
-                
-                Ok(url)
-            }
+    /// Hands `stream` to the configured store: the GCS and `file://` stores write it one chunk at
+    /// a time, so a large challenge binary/PCAP doesn't have to be fully resident in memory, while
+    /// the S3 and platform-proxy stores collect the whole stream before sending it. `len_hint`,
+    /// when known, lets a resumable backend report total progress/finalize the last chunk.
+    /// `options` controls the signed URL's expiry and response headers - see [`UploadOptions`].
+    pub async fn upload_stream<S>(
+        &self,
+        chall_id: &str,
+        filename: &str,
+        stream: S,
+        len_hint: Option<u64>,
+        options: UploadOptions,
+    ) -> Result<String>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + 'static,
+    {
+        let key = format!("{}/{}", chall_id, filename);
+        self.store
+            .put(&key, Box::pin(stream), len_hint, options.content_type.as_deref())
+            .await?;
+        self.store.signed_url(&key, &options).await
+    }
+
+    /// Content-addressed variant of [`Self::upload`]: the object is keyed by the SHA-256 of
+    /// `data` rather than `<chall_id>/<filename>`, so the same attachment reused across
+    /// challenges (or re-uploaded under a different name) is stored once. When an object already
+    /// exists under that digest, the upload is skipped and the existing object's URL is returned.
+    pub async fn upload_hashed(&self, data: Vec<u8>, options: UploadOptions) -> Result<HashedUpload> {
+        use sha2::Digest;
+
+        let sha256 = hex_encode(&sha2::Sha256::digest(&data));
+        let key = format!("{CAS_PREFIX}/{sha256}");
+
+        if !self.store.exists(&key).await? {
+            let len_hint = data.len() as u64;
+            let stream = futures_util::stream::once(async move { Ok(Bytes::from(data)) });
+            self.store
+                .put(&key, Box::pin(stream), Some(len_hint), options.content_type.as_deref())
+                .await?;
         }
+
+        let url = self.store.signed_url(&key, &options).await?;
+        Ok(HashedUpload { url, sha256 })
     }
-}
\ No newline at end of file
+}
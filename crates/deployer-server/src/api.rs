@@ -3,10 +3,12 @@ use std::collections::HashMap;
 use axum::{
     Json, Router,
     extract::{Path, State as StateE},
+    response::sse::{Event, KeepAlive, Sse},
     routing::{get, post},
 };
 use chrono::NaiveDateTime;
 use deployer_common::challenge::Challenge;
+use futures_util::StreamExt;
 use log::debug;
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
@@ -16,6 +18,7 @@ use crate::{
     Result, State,
     config::write_challenges_to_dir,
     deploy::{self, ChallengeDeployment},
+    exec,
 };
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -69,10 +72,19 @@ async fn deploy_challenge(
         payload.team_id,
         payload.challenge_id,
     ).fetch_optional(&mut *tx).await? {
-        // spawn experimental start task
         let challenge_deployment: ChallengeDeployment = challenge_deployment_row.try_into()?;
-        state.tasks.spawn(deploy::start_challenge_task(state.clone(), challenge_deployment));
-        
+        if !challenge_deployment.deployed {
+            // still mid-deploy (or the worker that was deploying it crashed) - nudge it along
+            // with another durable job rather than assuming the original one is still running
+            crate::deployment_jobs::enqueue_deploy(
+                &mut tx,
+                challenge_deployment.id,
+                payload.lifetime.unwrap_or(60 * 10),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
         // throw error
         return Err(crate::error::Error::AlreadyDeployed);
     }
@@ -88,17 +100,14 @@ async fn deploy_challenge(
         .await?
         .try_into()?;
 
+    // enqueue the deploy in the same transaction as the row insert, so a crash between the two
+    // can never happen - either both are durable or neither is
+    crate::deployment_jobs::enqueue_deploy(&mut tx, deployment.id, payload.lifetime.unwrap_or(60 * 10)).await?;
+
     tx.commit().await?;
 
     debug!("got back deployment {:?}", deployment);
 
-    // start deploying the chall
-    state.tasks.spawn(deploy::deploy_challenge_task(
-        state.clone(),
-        deployment.clone(),
-        payload.lifetime.unwrap_or(60 * 10)
-    ));
-
     Ok(Json(deployment.sanitize()))
 }
 
@@ -109,22 +118,23 @@ async fn destroy_challenge(
     StateE(state): StateE<State>,
     Json(payload): Json<ChallengeDeploymentReq>,
 ) -> Result<()> {
+    let mut tx = state.db.begin().await?;
+
     let deployment = match sqlx::query_as!(
         ChallengeDeploymentRow,
         "SELECT * FROM challenge_deployments WHERE team_id IS NOT DISTINCT FROM $1 AND challenge_id = $2 AND destroyed_at IS NULL",
         payload.team_id,
         payload.challenge_id,
     )
-        .fetch_optional(&state.db)
+        .fetch_optional(&mut *tx)
         .await? {
         None => return Ok(()),
         Some(d) => d,
     };
 
-    let deployment = deployment.try_into()?;
-    state
-        .tasks
-        .spawn(deploy::destroy_challenge_task(state.clone(), deployment));
+    let deployment: ChallengeDeployment = deployment.try_into()?;
+    crate::deployment_jobs::enqueue_destroy(&mut tx, deployment.id).await?;
+    tx.commit().await?;
 
     Ok(())
 }
@@ -145,6 +155,70 @@ async fn get_challenge(
     Ok(Json(deployment.sanitize()))
 }
 
+async fn load_deployment(state: &State, pub_id: &str) -> Result<ChallengeDeployment> {
+    Ok(sqlx::query_as!(
+        ChallengeDeploymentRow,
+        "SELECT * FROM challenge_deployments WHERE public_id = $1",
+        pub_id,
+    )
+    .fetch_one(&state.db)
+    .await?
+    .try_into()?)
+}
+
+/// Streams a deployed container's stdout/stderr as SSE, demuxing Docker's TTY frame headers.
+async fn stream_container_logs(
+    StateE(state): StateE<State>,
+    Path((pub_id, ct)): Path<(String, String)>,
+) -> Result<Sse<impl futures_util::Stream<Item = std::result::Result<Event, std::convert::Infallible>>>> {
+    let chall = load_deployment(&state, &pub_id).await?;
+    let (ctx, container_name) = deploy::resolve_container(&state, &chall, &ct).await?;
+
+    let stream = exec::stream_logs(&ctx, &container_name, true, "100").map(|line| {
+        let event = match line {
+            Ok(line) => Event::default()
+                .event(line.stream)
+                .data(line.message),
+            Err(e) => Event::default().event("error").data(e.to_string()),
+        };
+        Ok(event)
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize)]
+struct ExecRequest {
+    cmd: Vec<String>,
+}
+
+/// Runs a one-shot command in a deployed container and returns its collected output.
+async fn exec_in_container(
+    StateE(state): StateE<State>,
+    Path((pub_id, ct)): Path<(String, String)>,
+    Json(payload): Json<ExecRequest>,
+) -> Result<Json<exec::ExecResult>> {
+    let chall = load_deployment(&state, &pub_id).await?;
+    let (ctx, container_name) = deploy::resolve_container(&state, &chall, &ct).await?;
+
+    let result = exec::run_exec(&ctx, &container_name, payload.cmd).await?;
+
+    Ok(Json(result))
+}
+
+/// Returns the most recent stats sample for every instanced container currently being watched by
+/// `stats_monitor`, so operators can see which teams are hammering a challenge.
+async fn live_stats(
+    StateE(state): StateE<State>,
+) -> Json<Vec<crate::stats_monitor::LiveStats>> {
+    Json(state.live_stats.read().await.values().cloned().collect())
+}
+
+/// Exposes the deployer's Prometheus metrics for scraping.
+async fn metrics() -> String {
+    crate::metrics::render()
+}
+
 async fn reload_challenges(StateE(state): StateE<State>) -> Result<()> {
     debug!("Reloading challenges");
     let mut challs_new = crate::config::load_challenges_from_dir(&state.config.challenges_dir)?;
@@ -174,9 +248,13 @@ async fn load_challenges(
 
 pub fn router() -> Router<crate::State> {
     Router::new()
+        .route("/metrics", get(metrics))
         .route("/challenges/reload", post(reload_challenges))
         .route("/challenges/load", post(load_challenges))
         .route("/challenge/deploy", post(deploy_challenge))
         .route("/challenge/destroy", post(destroy_challenge))
         .route("/deployment/{id}", get(get_challenge))
+        .route("/deployment/{id}/{container}/logs", get(stream_container_logs))
+        .route("/deployment/{id}/{container}/exec", post(exec_in_container))
+        .route("/stats/live", get(live_stats))
 }
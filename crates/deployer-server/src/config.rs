@@ -2,10 +2,12 @@ use std::{
     collections::HashMap,
     fs::File,
     io::Write,
+    net::ToSocketAddrs,
     ops::Deref,
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
+    time::Instant,
 };
 
 use deployer_common::challenge::{
@@ -44,26 +46,72 @@ pub struct CaddyKeychain {
 }
 
 impl CaddyKeychain {
+    /// Builds an mTLS-verified client for this host's Caddy admin API. The server cert chain is
+    /// validated against `mtls.cacert` like any other TLS connection; `mtls.expected_san` exists
+    /// because Caddy's cert is usually issued for an internal name rather than the literal
+    /// `endpoint` host (e.g. a Docker service name resolving differently than the SAN). When set,
+    /// we resolve that expected name to wherever `endpoint` actually points and address the admin
+    /// API by the expected name, so hostname verification succeeds against the real cert instead
+    /// of being disabled outright.
     pub fn as_client(&self) -> crate::Result<reqwest::Client> {
-        Ok(reqwest::ClientBuilder::new()
+        let mut builder = reqwest::ClientBuilder::new()
             .tls_built_in_root_certs(false)
             .tls_built_in_webpki_certs(false)
-            // FIXME(ani): currently not verifying against ca certs because caddy sucks
-            .add_root_certificate(reqwest::Certificate::from_pem(self.mtls.cacert.as_bytes())?)
-            .danger_accept_invalid_hostnames(true)
-            .identity(reqwest::Identity::from_pem(
-                format!("{}\n{}", self.mtls.key, self.mtls.cert).as_bytes(),
-            )?)
-            .use_rustls_tls()
-            .build()?)
+            .add_root_certificate(
+                reqwest::Certificate::from_pem(self.mtls.cacert.as_bytes()).map_err(|e| {
+                    crate::error::Error::TlsIdentity(format!("invalid CA certificate: {e}"))
+                })?,
+            )
+            .identity(
+                reqwest::Identity::from_pem(
+                    format!("{}\n{}", self.mtls.key, self.mtls.cert).as_bytes(),
+                )
+                .map_err(|e| {
+                    crate::error::Error::TlsIdentity(format!("invalid client identity: {e}"))
+                })?,
+            )
+            .use_rustls_tls();
+
+        if let Some(expected_san) = &self.mtls.expected_san {
+            builder = builder.resolve(expected_san, self.endpoint_addr()?);
+        }
+
+        Ok(builder.build()?)
     }
 
-    pub fn prep_url(&self, path: &str) -> reqwest::Url {
-        // unwrap bad
-        reqwest::Url::parse(&self.endpoint)
-            .unwrap()
-            .join(path)
-            .unwrap()
+    /// The host:port `endpoint` actually resolves to, used to point `resolve()` at the real
+    /// Caddy instance while addressing it by `expected_san` on the wire.
+    fn endpoint_addr(&self) -> crate::Result<std::net::SocketAddr> {
+        let url = reqwest::Url::parse(&self.endpoint)
+            .map_err(|e| crate::error::Error::InvalidUrl(format!("{}: {e}", self.endpoint)))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| crate::error::Error::InvalidUrl(format!("{} has no host", self.endpoint)))?;
+        let port = url.port_or_known_default().unwrap_or(443);
+
+        (host, port)
+            .to_socket_addrs()
+            .map_err(|e| {
+                crate::error::Error::TlsIdentity(format!("failed to resolve {host}:{port}: {e}"))
+            })?
+            .next()
+            .ok_or_else(|| {
+                crate::error::Error::TlsIdentity(format!("no addresses found for {host}:{port}"))
+            })
+    }
+
+    pub fn prep_url(&self, path: &str) -> crate::Result<reqwest::Url> {
+        let mut url = reqwest::Url::parse(&self.endpoint)
+            .map_err(|e| crate::error::Error::InvalidUrl(format!("{}: {e}", self.endpoint)))?;
+
+        if let Some(expected_san) = &self.mtls.expected_san {
+            url.set_host(Some(expected_san)).map_err(|e| {
+                crate::error::Error::InvalidUrl(format!("expected_san {expected_san:?}: {e}"))
+            })?;
+        }
+
+        url.join(path)
+            .map_err(|e| crate::error::Error::InvalidUrl(format!("{path}: {e}")))
     }
 }
 
@@ -75,6 +123,9 @@ pub struct ClientTLSKeychain {
     pub cert: String,
     // client key (pem)
     pub key: String,
+    // overrides the hostname used for TLS verification and SNI, for when the cert's SAN doesn't
+    // match the literal `endpoint` host
+    pub expected_san: Option<String>,
 }
 
 pub struct HostKeychainEnv(HashMap<String, HostKeychain>);
@@ -129,6 +180,27 @@ pub struct Config {
     pub host_keychains: HostKeychainEnv,
     #[envconfig(from = "CHALLENGES_DIR")]
     pub challenges_dir: PathBuf,
+
+    // instanced containers whose CPU or memory usage stays at or above these percentages for
+    // `STATS_CONSECUTIVE_SAMPLES` samples in a row get auto-killed by the stats monitor
+    #[envconfig(from = "STATS_CPU_THRESHOLD_PERCENT", default = "90.0")]
+    pub stats_cpu_threshold_percent: f64,
+
+    #[envconfig(from = "STATS_MEM_THRESHOLD_PERCENT", default = "90.0")]
+    pub stats_mem_threshold_percent: f64,
+
+    #[envconfig(from = "STATS_CONSECUTIVE_SAMPLES", default = "3")]
+    pub stats_consecutive_samples: u32,
+
+    // how often the stats monitor scans for newly-live instanced deployments to start watching
+    #[envconfig(from = "STATS_SCAN_INTERVAL_SECS", default = "10")]
+    pub stats_scan_interval_secs: u64,
+
+    // how long a pooled Caddy client is trusted before `expected_san` is re-resolved and the
+    // client rebuilt - the resolved IP is a Docker service name, which moves every time that
+    // container restarts or gets recreated, so caching it forever eventually points at a dead IP
+    #[envconfig(from = "CADDY_CLIENT_TTL_SECS", default = "60")]
+    pub caddy_client_ttl_secs: u64,
 }
 
 pub fn load_challenges_from_dir(dir: &Path) -> eyre::Result<HashMap<String, Challenge>> {
@@ -211,6 +283,44 @@ pub struct StateInner {
     pub challenge_data: RwLock<HashMap<String, Challenge>>,
     pub db: PgPool,
     pub tasks: TaskTracker,
+    // one pooled, mTLS-verified reqwest::Client per HostKeychain id, built lazily on first use so
+    // deploy/destroy/reap calls reuse connections instead of reconstructing TLS state every time.
+    // Paired with the `Instant` it was built at, since `expected_san` resolves to a Docker service
+    // name that moves around - `caddy_client` rebuilds the entry once it's older than
+    // `config.caddy_client_ttl_secs` rather than trusting the resolved IP forever.
+    pub caddy_clients: RwLock<HashMap<String, (Instant, Arc<reqwest::Client>)>>,
+    // most recent stats sample per live instanced container, keyed by Docker container id, fed by
+    // `stats_monitor::poll_loop`'s per-instance watchers
+    pub live_stats: RwLock<HashMap<String, crate::stats_monitor::LiveStats>>,
 }
 
 pub type State = Arc<StateInner>;
+
+/// Returns the pooled Caddy client for `host_key`, building (and caching) it on first use and
+/// rebuilding it once it's older than `config.caddy_client_ttl_secs` - `expected_san` resolves to
+/// wherever the Caddy container's Docker service name currently points, and that can change after
+/// a restart/recreate, so the pool can't just cache the first resolution forever.
+pub async fn caddy_client(state: &State, host_key: &str) -> crate::Result<Arc<reqwest::Client>> {
+    let ttl = std::time::Duration::from_secs(state.config.caddy_client_ttl_secs);
+
+    if let Some((built_at, client)) = state.caddy_clients.read().await.get(host_key) {
+        if built_at.elapsed() < ttl {
+            return Ok(client.clone());
+        }
+    }
+
+    let host_keychain = state
+        .config
+        .host_keychains
+        .get(host_key)
+        .ok_or_else(|| crate::error::Error::UnknownHost(host_key.to_owned()))?;
+    let client = Arc::new(host_keychain.caddy.as_client()?);
+
+    state
+        .caddy_clients
+        .write()
+        .await
+        .insert(host_key.to_owned(), (Instant::now(), client.clone()));
+
+    Ok(client)
+}
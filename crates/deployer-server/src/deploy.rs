@@ -16,7 +16,7 @@ use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::{State, api::ChallengeDeploymentRow, config::CaddyKeychain};
+use crate::{State, config::CaddyKeychain};
 
 /* db models (sorta) */
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -100,7 +100,14 @@ fn calculate_network_name(
     }
 }
 
-fn get_unused_port() -> u16 {
+fn strategy_label(strategy: DeploymentStrategy) -> &'static str {
+    match strategy {
+        DeploymentStrategy::Static => "static",
+        DeploymentStrategy::Instanced => "instanced",
+    }
+}
+
+pub(crate) fn get_unused_port() -> u16 {
     loop {
         if let Ok(l) = std::net::TcpListener::bind(("0.0.0.0", 0)) {
             return l.local_addr().unwrap().port();
@@ -152,18 +159,22 @@ pub(crate) fn calculate_static_tcp_port(
 #[derive(Debug, Clone)]
 struct DockerGuard {
     ctx: Arc<DeployableContext>,
+    pool: sqlx::PgPool,
     containers: Vec<String>,
     networks: Vec<String>,
+    ports: Vec<i32>,
     committed: bool,
     dropping: bool,
 }
 
 impl DockerGuard {
-    pub fn new(ctx: Arc<DeployableContext>) -> Self {
+    pub fn new(ctx: Arc<DeployableContext>, pool: sqlx::PgPool) -> Self {
         Self {
             ctx,
+            pool,
             containers: vec![],
             networks: vec![],
+            ports: vec![],
             committed: false,
             dropping: false,
         }
@@ -177,6 +188,15 @@ impl DockerGuard {
         self.networks.push(n.to_owned());
     }
 
+    /// Tracks a freshly-created [`crate::ports::PortReservation`] so it's released if this
+    /// deploy never commits. Reused static reservations are not tracked here, since rolling
+    /// back a failed redeploy shouldn't tear down an already-stable port mapping.
+    pub fn port(&mut self, reservation: crate::ports::PortReservation) {
+        if reservation.freshly_created {
+            self.ports.push(reservation.id);
+        }
+    }
+
     pub fn commit(&mut self) {
         self.committed = true;
     }
@@ -186,6 +206,10 @@ impl DockerGuard {
             return;
         }
 
+        crate::metrics::GUARD_ROLLBACKS
+            .with_label_values(&["docker"])
+            .inc();
+
         for c in self.containers.iter().rev() {
             self.ctx
                 .docker
@@ -205,6 +229,10 @@ impl DockerGuard {
         for n in self.networks.iter().rev() {
             self.ctx.docker.remove_network(n).await.ok();
         }
+
+        for id in self.ports.iter().rev() {
+            crate::ports::release(&self.pool, *id).await;
+        }
     }
 }
 
@@ -254,9 +282,16 @@ impl CaddyGuard {
             return;
         }
 
+        crate::metrics::GUARD_ROLLBACKS
+            .with_label_values(&["caddy"])
+            .inc();
+
         for r in self.routes.iter().rev() {
+            let Ok(url) = self.kc.prep_url("/dynamic-router/delete") else {
+                continue;
+            };
             self.client
-                .post(self.kc.prep_url("/dynamic-router/delete"))
+                .post(url)
                 .json(&json!({
                     "host": r,
                 }))
@@ -319,19 +354,54 @@ pub async fn deploy_challenge(
         )
     })?;
 
+    let metric_labels = [chall_data.id.clone(), strategy_label(chall_data.strategy).to_owned()];
+    crate::metrics::DEPLOY_ATTEMPTS
+        .with_label_values(&[&metric_labels[0], &metric_labels[1]])
+        .inc();
+    let deploy_started = std::time::Instant::now();
+
+    let result = deploy_challenge_steps(
+        &state,
+        tx,
+        &chall,
+        &chall_data,
+        public_team_id,
+        default_container_lifetime,
+    )
+    .await;
+
+    crate::metrics::DEPLOY_LATENCY_SECONDS
+        .with_label_values(&[&metric_labels[0], &metric_labels[1]])
+        .observe(deploy_started.elapsed().as_secs_f64());
+    match &result {
+        Ok(()) => crate::metrics::DEPLOY_SUCCESSES.with_label_values(&[&metric_labels[0], &metric_labels[1]]).inc(),
+        Err(_) => crate::metrics::DEPLOY_FAILURES.with_label_values(&[&metric_labels[0], &metric_labels[1]]).inc(),
+    };
+
+    result
+}
+
+async fn deploy_challenge_steps(
+    state: &State,
+    tx: &mut sqlx::PgTransaction<'_>,
+    chall: &ChallengeDeployment,
+    chall_data: &deployer_common::challenge::Challenge,
+    public_team_id: Option<String>,
+    default_container_lifetime: u64,
+) -> eyre::Result<()> {
     // 3. ensure there is a container on it
     let Some(chall_containers) = &chall_data.container else {
         return Err(eyre!("challenge {} does not have container", chall_data.id));
     };
 
     // 4. connect to the appropriate docker socket
-    let host_keychain =
-        &state.config.host_keychains[chall_data.host.as_deref().unwrap_or("default")];
+    let host_key = chall_data.host.as_deref().unwrap_or("default");
+    let host_keychain = &state.config.host_keychains[host_key];
     let ctx: Arc<DeployableContext> = Arc::new(host_keychain.docker.clone().try_into()?);
 
     // think these steps can be repeated for each container (perhaps create a network?)
-    let mut _docker_guard = DockerGuard::new(ctx.clone());
-    let caddy_client = Arc::new(host_keychain.caddy.as_client()?);
+    let mut _docker_guard = DockerGuard::new(ctx.clone(), state.db.clone());
+    let caddy_client = crate::config::caddy_client(state, host_key).await?;
     let mut _caddy_guard = CaddyGuard::new(caddy_client.clone(), host_keychain.caddy.clone());
 
     let mut deploy_data = HashMap::new();
@@ -378,18 +448,25 @@ pub async fn deploy_challenge(
             for (&p, &t) in expose {
                 match t {
                     ExposeType::Tcp => {
+                        let team_id = match chall_data.strategy {
+                            DeploymentStrategy::Static => None,
+                            DeploymentStrategy::Instanced => chall.team_id,
+                        };
+                        let reservation = crate::ports::allocate_tcp_port(
+                            tx,
+                            host_key,
+                            &chall_data.id,
+                            ct,
+                            p,
+                            team_id,
+                        )
+                        .await?;
+                        _docker_guard.port(reservation);
+
                         mappings.insert(
                             p,
                             HostMapping::Tcp {
-                                port: match chall_data.strategy {
-                                    DeploymentStrategy::Static => calculate_static_tcp_port(
-                                        &chall_data.id,
-                                        &ct,
-                                        p,
-                                        chall_data.bump_seed,
-                                    ),
-                                    _ => get_unused_port(),
-                                },
+                                port: reservation.port,
                                 base: host_keychain.caddy.base.clone(),
                             },
                         );
@@ -485,6 +562,46 @@ pub async fn deploy_challenge(
                         ),
                         cap_add: chall_container.cap_add.clone(),
                         privileged: chall_container.privileged.clone(),
+                        shm_size: chall_container.security.shm_size,
+                        pids_limit: chall_container.security.pids_limit,
+                        memory_swap: chall_container.security.memory_swap,
+                        ulimits: (!chall_container.security.ulimits.is_empty()).then(|| {
+                            chall_container
+                                .security
+                                .ulimits
+                                .iter()
+                                .map(|u| bollard::models::ResourcesUlimits {
+                                    name: Some(u.name.clone()),
+                                    soft: Some(u.soft),
+                                    hard: Some(u.hard),
+                                })
+                                .collect()
+                        }),
+                        cgroupns_mode: chall_container
+                            .security
+                            .cgroupns_mode
+                            .as_deref()
+                            .and_then(|m| m.parse().ok()),
+                        userns_mode: chall_container.security.userns_mode.clone(),
+                        security_opt: (!chall_container.security.security_opt.is_empty())
+                            .then(|| chall_container.security.security_opt.clone()),
+                        readonly_rootfs: Some(chall_container.security.read_only),
+                        tmpfs: chall_container.security.read_only.then(|| {
+                            chall_container
+                                .security
+                                .tmpfs
+                                .iter()
+                                .map(|p| (p.to_string_lossy().into_owned(), String::new()))
+                                .collect()
+                        }),
+                        extra_hosts: (!chall_container.security.extra_hosts.is_empty()).then(|| {
+                            chall_container
+                                .security
+                                .extra_hosts
+                                .iter()
+                                .map(|(host, ip)| format!("{}:{}", host, ip))
+                                .collect()
+                        }),
                         ..Default::default()
                     }),
                     ..Default::default()
@@ -492,6 +609,7 @@ pub async fn deploy_challenge(
             )
             .await?;
         _docker_guard.container(&container_name);
+        crate::metrics::LIVE_CONTAINERS.with_label_values(&[host_key]).inc();
 
         debug!("starting container");
 
@@ -521,6 +639,11 @@ pub async fn deploy_challenge(
                 .ok_or_else(|| eyre!("Container has no IP address"))?
         };
 
+        // 8.5. wait for the container to actually be ready before publishing any routes for it
+        if let Some(probe) = &chall_container.readiness {
+            crate::readiness::await_readiness(probe, &ctx, &container_name, &container_ip).await?;
+        }
+
         debug!("creating caddy client");
 
         // 9. ??? update caddy or something somehow
@@ -529,14 +652,14 @@ pub async fn deploy_challenge(
             if let HostMapping::Http { subdomain, .. } = &map {
                 let host = format!("{}.{}", subdomain, host_keychain.caddy.base);
                 caddy_client
-                    .post(host_keychain.caddy.prep_url("/dynamic-router/delete"))
+                    .post(host_keychain.caddy.prep_url("/dynamic-router/delete")?)
                     .json(&json!({
                         "host": host,
                     }))
                     .send()
                     .await?;
                 caddy_client
-                    .post(host_keychain.caddy.prep_url("/dynamic-router/add"))
+                    .post(host_keychain.caddy.prep_url("/dynamic-router/add")?)
                     .json(&json!({
                         "host": host,
                         "upstream": format!("{}:{}", container_ip, p),
@@ -579,42 +702,33 @@ pub async fn deploy_challenge(
         .execute(&mut **tx)
         .await?;
 
-    // 12. spawn a task to destroy the challenge after the expiration duration (todo)
+    // 12. enqueue a durable expire job instead of a bare sleeping tokio task, so the expiration
+    // survives a restart of this process
     if let Some(expiration_time) = new_expiration_time {
-        let dur = (expiration_time - chrono::Utc::now().naive_utc())
-            .to_std()
-            .unwrap();
-        let state2 = state.clone();
-        let chall2 = sqlx::query_as!(
-            ChallengeDeploymentRow,
-            "SELECT * FROM challenge_deployments WHERE id = $1",
-            chall.id,
-        )
-        .fetch_one(&mut **tx)
-        .await?
-        .try_into()?;
-        tokio::spawn(async move {
-            tokio::time::sleep(dur).await;
-            destroy_challenge_task(state2, chall2, true).await;
-        });
+        crate::deployment_jobs::enqueue_expire(tx, chall.id, expiration_time).await?;
     }
 
     _docker_guard.commit();
     _caddy_guard.commit();
+    crate::metrics::LIVE_DEPLOYMENTS.with_label_values(&[host_key]).inc();
     Ok(())
 }
 
-pub async fn deploy_challenge_task(state: State, chall: ChallengeDeployment, default_container_lifetime: u64) {
-    let mut tx = state.db.begin().await.unwrap();
-    if let Err(e) = deploy_challenge(state, &mut tx, chall.clone(), default_container_lifetime).await {
+pub async fn deploy_challenge_task(
+    state: State,
+    chall: ChallengeDeployment,
+    default_container_lifetime: u64,
+) -> eyre::Result<()> {
+    let mut tx = state.db.begin().await?;
+    let result = deploy_challenge(state, &mut tx, chall.clone(), default_container_lifetime).await;
+    if let Err(e) = &result {
         error!("Failed to deploy challenge {:?}: {:?}", chall, e);
-        sqlx::query!("DELETE FROM challenge_deployments WHERE id = $1", chall.id,)
+        sqlx::query!("DELETE FROM challenge_deployments WHERE id = $1", chall.id)
             .execute(&mut *tx)
-            // idk
-            .await
-            .unwrap();
+            .await?;
     }
-    tx.commit().await.unwrap();
+    tx.commit().await?;
+    result
 }
 
 pub async fn destroy_challenge(
@@ -664,16 +778,41 @@ pub async fn destroy_challenge(
         _ => return Ok(()),
     };
 
+    let metric_labels = [chall_data.id.clone(), strategy_label(chall_data.strategy).to_owned()];
+    crate::metrics::DESTROY_ATTEMPTS
+        .with_label_values(&[&metric_labels[0], &metric_labels[1]])
+        .inc();
+    let destroy_started = std::time::Instant::now();
+
+    let result = destroy_challenge_steps(&state, &chall, &chall_data, deploy_data).await;
+
+    crate::metrics::DESTROY_LATENCY_SECONDS
+        .with_label_values(&[&metric_labels[0], &metric_labels[1]])
+        .observe(destroy_started.elapsed().as_secs_f64());
+    match &result {
+        Ok(()) => crate::metrics::DESTROY_SUCCESSES.with_label_values(&[&metric_labels[0], &metric_labels[1]]).inc(),
+        Err(_) => crate::metrics::DESTROY_FAILURES.with_label_values(&[&metric_labels[0], &metric_labels[1]]).inc(),
+    };
+
+    result
+}
+
+async fn destroy_challenge_steps(
+    state: &State,
+    chall: &ChallengeDeployment,
+    chall_data: &deployer_common::challenge::Challenge,
+    deploy_data: &DeploymentData,
+) -> eyre::Result<()> {
     // 3. ensure there is a container on it
     let Some(chall_containers) = &chall_data.container else {
         return Ok(());
     };
 
     // 4. connect to the appropriate docker socket
-    let host_keychain =
-        &state.config.host_keychains[chall_data.host.as_deref().unwrap_or("default")];
+    let host_key = chall_data.host.as_deref().unwrap_or("default");
+    let host_keychain = &state.config.host_keychains[host_key];
     let ctx: DeployableContext = host_keychain.docker.clone().try_into()?;
-    let caddy_client = host_keychain.caddy.as_client()?;
+    let caddy_client = crate::config::caddy_client(state, host_key).await?;
 
     // think these steps can be repeated for each container (perhaps create a network?)
     for (ct, _chall_container) in chall_containers {
@@ -689,7 +828,7 @@ pub async fn destroy_challenge(
                 if let HostMapping::Http { subdomain, .. } = &map {
                     let host = format!("{}.{}", subdomain, host_keychain.caddy.base);
                     caddy_client
-                        .post(host_keychain.caddy.prep_url("/dynamic-router/delete"))
+                        .post(host_keychain.caddy.prep_url("/dynamic-router/delete")?)
                         .json(&json!({
                             "host": host,
                         }))
@@ -712,23 +851,107 @@ pub async fn destroy_challenge(
             )
             .await
             .ok();
+        crate::metrics::LIVE_CONTAINERS.with_label_values(&[host_key]).dec();
+
+        if let Some(team_id) = chall.team_id {
+            crate::ports::release_instanced(&state.db, host_key, &chall_data.id, ct, team_id).await;
+        }
     }
 
     /* TODO: delete network */
     let network_name = calculate_network_name(&chall_data.id, chall_data.strategy, chall.team_id);
     ctx.docker.remove_network(&network_name).await.ok();
 
+    crate::metrics::LIVE_DEPLOYMENTS.with_label_values(&[host_key]).dec();
+
     // done... how nice
 
     Ok(())
 }
 
-pub async fn destroy_challenge_task(state: State, chall: ChallengeDeployment, automatic: bool) {
-    let mut tx = state.db.begin().await.unwrap();
-    if let Err(e) = destroy_challenge(state, &mut tx, chall.clone(), automatic).await {
-        error!("Failed to destroy challenge {:?}: {:?}", chall, e);
-        // don't commit the tx
-    } else {
-        tx.commit().await.unwrap();
+/// Resolves the live `DeployableContext`/container name for a given deployment + container key,
+/// using the same lookups `deploy_challenge`/`destroy_challenge` use. Shared by the logs/exec
+/// subsystem so it targets the exact container a deployment is running.
+pub async fn resolve_container(
+    state: &State,
+    chall: &ChallengeDeployment,
+    ct: &str,
+) -> eyre::Result<(Arc<DeployableContext>, String)> {
+    let public_chall_partial = sqlx::query!(
+        "SELECT public_id FROM challenges WHERE id = $1",
+        chall.challenge_id
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    let chall_data = {
+        let rg = state.challenge_data.read().await;
+        rg.get(&public_chall_partial.public_id).map(Clone::clone)
+    }
+    .ok_or_else(|| {
+        eyre!(
+            "failed to get challenge data for {}",
+            public_chall_partial.public_id
+        )
+    })?;
+
+    let host_keychain =
+        &state.config.host_keychains[chall_data.host.as_deref().unwrap_or("default")];
+    let ctx: Arc<DeployableContext> = Arc::new(host_keychain.docker.clone().try_into()?);
+
+    let container_name =
+        calculate_container_name(&chall_data.id, chall_data.strategy, ct, chall.team_id);
+
+    Ok((ctx, container_name))
+}
+
+pub async fn destroy_challenge_task(
+    state: State,
+    chall: ChallengeDeployment,
+    automatic: bool,
+) -> eyre::Result<()> {
+    let mut tx = state.db.begin().await?;
+    let result = destroy_challenge(state, &mut tx, chall.clone(), automatic).await;
+    match &result {
+        Err(e) => error!("Failed to destroy challenge {:?}: {:?}", chall, e), // don't commit the tx
+        Ok(()) => tx.commit().await?,
+    }
+    result
+}
+
+#[cfg(test)]
+mod static_port_tests {
+    use super::calculate_static_tcp_port;
+
+    #[test]
+    fn is_deterministic_for_the_same_inputs() {
+        let a = calculate_static_tcp_port("my-chall", "default", 80, 0);
+        let b = calculate_static_tcp_port("my-chall", "default", 80, 0);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn rehash_walk_produces_different_candidates_per_bump() {
+        let candidates: std::collections::HashSet<u16> = (0..8)
+            .map(|bump| calculate_static_tcp_port("my-chall", "default", 80, bump))
+            .collect();
+        // not strictly guaranteed to be all-distinct (it's a hash), but collapsing to a single
+        // value across 8 bumps would mean the rehash walk is broken, not just unlucky
+        assert!(candidates.len() > 1);
+    }
+
+    #[test]
+    fn never_allocates_a_reserved_low_port() {
+        for bump in 0..64 {
+            let port = calculate_static_tcp_port("my-chall", "default", 80, bump);
+            assert!(port >= 1025);
+        }
+    }
+
+    #[test]
+    fn different_containers_get_different_candidates() {
+        let a = calculate_static_tcp_port("my-chall", "default", 80, 0);
+        let b = calculate_static_tcp_port("my-chall", "other", 80, 0);
+        assert_ne!(a, b);
     }
 }
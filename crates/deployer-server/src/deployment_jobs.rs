@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    State,
+    api::ChallengeDeploymentRow,
+    deploy::{deploy_challenge_task, destroy_challenge_task},
+};
+
+/// How long a `running` row can go without a heartbeat before we assume its worker died and the
+/// job is reclaimable.
+const STALE_HEARTBEAT_SECS: i64 = 60;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+/// What a `deployment_jobs` row asks a worker to do. Generalizes the old `reap_queue` (which
+/// only ever scheduled a future `destroy`) so the actual deploy/destroy work - previously a bare
+/// `state.tasks.spawn(...)` that a restart would silently drop - survives restarts too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "deployment_job_kind", rename_all = "lowercase")]
+pub enum JobKind {
+    Deploy,
+    Destroy,
+    Expire,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct DeployPayload {
+    #[serde(default)]
+    lifetime: Option<u64>,
+}
+
+/// Enqueues a `deploy` job for `deployment_id`, to run as soon as a worker is free.
+pub async fn enqueue_deploy(
+    tx: &mut sqlx::PgTransaction<'_>,
+    deployment_id: i32,
+    default_container_lifetime: u64,
+) -> eyre::Result<()> {
+    let payload = serde_json::to_value(DeployPayload { lifetime: Some(default_container_lifetime) })?;
+    sqlx::query!(
+        "INSERT INTO deployment_jobs (kind, deployment_id, payload) VALUES ('deploy', $1, $2)",
+        deployment_id,
+        payload,
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Enqueues a `destroy` job for `deployment_id`, to run as soon as a worker is free.
+pub async fn enqueue_destroy(tx: &mut sqlx::PgTransaction<'_>, deployment_id: i32) -> eyre::Result<()> {
+    sqlx::query!(
+        "INSERT INTO deployment_jobs (kind, deployment_id) VALUES ('destroy', $1)",
+        deployment_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Schedules an instanced deployment for teardown at `run_at`, replacing the old
+/// `tokio::spawn(sleep(...))` pattern with a durable row so a restart can't lose the expiration.
+pub async fn enqueue_expire(
+    tx: &mut sqlx::PgTransaction<'_>,
+    deployment_id: i32,
+    run_at: NaiveDateTime,
+) -> eyre::Result<()> {
+    sqlx::query!(
+        "INSERT INTO deployment_jobs (kind, deployment_id, run_at) VALUES ('expire', $1, $2)",
+        deployment_id,
+        run_at,
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+struct ClaimedJob {
+    id: Uuid,
+    kind: JobKind,
+    deployment_id: i32,
+    payload: serde_json::Value,
+    attempts: i32,
+}
+
+async fn claim_one(state: &State) -> eyre::Result<Option<ClaimedJob>> {
+    let mut tx = state.db.begin().await?;
+
+    let claimed = sqlx::query!(
+        r#"UPDATE deployment_jobs SET status = 'running', heartbeat_at = NOW()
+           WHERE id = (
+               SELECT id FROM deployment_jobs
+               WHERE run_at <= NOW() AND (
+                   status = 'new'
+                   OR (status = 'running' AND heartbeat_at < NOW() - make_interval(secs => $1))
+               )
+               ORDER BY run_at
+               FOR UPDATE SKIP LOCKED
+               LIMIT 1
+           )
+           RETURNING id, kind AS "kind: JobKind", deployment_id, payload, attempts"#,
+        STALE_HEARTBEAT_SECS as f64,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(claimed.map(|row| ClaimedJob {
+        id: row.id,
+        kind: row.kind,
+        deployment_id: row.deployment_id,
+        payload: row.payload,
+        attempts: row.attempts,
+    }))
+}
+
+/// Runs the deploy/destroy call for a claimed job, keeping its `heartbeat_at` fresh for the
+/// duration so a crash mid-call doesn't leave the row stuck as `running` forever.
+async fn run_job(state: State, job: ClaimedJob) {
+    let job_id = job.id;
+    let db = state.db.clone();
+    let heartbeat_task = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+            let _ = sqlx::query!("UPDATE deployment_jobs SET heartbeat_at = NOW() WHERE id = $1", job_id)
+                .execute(&db)
+                .await;
+        }
+    });
+
+    let result = process_job(&state, &job).await;
+    heartbeat_task.abort();
+
+    match result {
+        Ok(()) => {
+            if let Err(e) = sqlx::query!("DELETE FROM deployment_jobs WHERE id = $1", job_id)
+                .execute(&state.db)
+                .await
+            {
+                error!("deployment_jobs: failed to delete completed job {}: {:?}", job_id, e);
+            }
+        }
+        Err(e) => {
+            let attempts = job.attempts + 1;
+            let backoff_secs = 2i64.saturating_pow(attempts as u32).min(MAX_BACKOFF_SECS);
+            error!(
+                "deployment_jobs: job {} ({:?}) for deployment {} failed, retrying in {}s (attempt {}): {:?}",
+                job_id, job.kind, job.deployment_id, backoff_secs, attempts, e
+            );
+            if let Err(e) = sqlx::query!(
+                "UPDATE deployment_jobs SET status = 'new', attempts = $2, run_at = NOW() + make_interval(secs => $3) WHERE id = $1",
+                job_id,
+                attempts,
+                backoff_secs as f64,
+            )
+            .execute(&state.db)
+            .await
+            {
+                error!("deployment_jobs: failed to reschedule job {}: {:?}", job_id, e);
+            }
+        }
+    }
+}
+
+async fn process_job(state: &State, job: &ClaimedJob) -> eyre::Result<()> {
+    let deployment_row = sqlx::query_as!(
+        ChallengeDeploymentRow,
+        "SELECT * FROM challenge_deployments WHERE id = $1",
+        job.deployment_id,
+    )
+    .fetch_one(&state.db)
+    .await?;
+    let deployment = deployment_row.try_into()?;
+
+    match job.kind {
+        JobKind::Deploy => {
+            let payload: DeployPayload = serde_json::from_value(job.payload.clone()).unwrap_or_default();
+            deploy_challenge_task(state.clone(), deployment, payload.lifetime.unwrap_or(60 * 10)).await
+        }
+        JobKind::Destroy => destroy_challenge_task(state.clone(), deployment, false).await,
+        JobKind::Expire => destroy_challenge_task(state.clone(), deployment, true).await,
+    }
+}
+
+/// Background worker loop: polls for due/overdue jobs and runs them. Safe to run several of
+/// these concurrently (claiming is done with `FOR UPDATE SKIP LOCKED`).
+pub async fn worker_loop(state: State) {
+    loop {
+        if let Ok(row) = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM deployment_jobs WHERE kind = 'expire'"#)
+            .fetch_one(&state.db)
+            .await
+        {
+            crate::metrics::PENDING_EXPIRATIONS.set(row.count);
+        }
+
+        match claim_one(&state).await {
+            Ok(Some(job)) => {
+                debug!("deployment_jobs: claimed {:?} job for deployment {}", job.kind, job.deployment_id);
+                run_job(state.clone(), job).await;
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => error!("deployment_jobs: error claiming job: {:?}", e),
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
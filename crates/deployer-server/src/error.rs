@@ -0,0 +1,53 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{0}")]
+    Database(#[from] sqlx::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Deploy(#[from] reqwest::Error),
+    #[error("Caddy TLS identity error: {0}")]
+    TlsIdentity(String),
+    #[error("Invalid Caddy URL: {0}")]
+    InvalidUrl(String),
+    #[error("Unknown host keychain {0:?}")]
+    UnknownHost(String),
+    #[error("Deployment already in progress")]
+    AlreadyDeployed,
+    #[error("{0}")]
+    Other(#[from] eyre::Error),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Serialize)]
+pub struct ErrorResponse<'a> {
+    error: &'a str,
+    message: String,
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        let (status, error) = match self {
+            Error::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
+            Error::Json(_) => (StatusCode::INTERNAL_SERVER_ERROR, "json_error"),
+            Error::Deploy(_) => (StatusCode::BAD_GATEWAY, "deploy_error"),
+            Error::TlsIdentity(_) => (StatusCode::INTERNAL_SERVER_ERROR, "tls_identity_error"),
+            Error::InvalidUrl(_) => (StatusCode::INTERNAL_SERVER_ERROR, "invalid_url"),
+            Error::UnknownHost(_) => (StatusCode::INTERNAL_SERVER_ERROR, "unknown_host"),
+            Error::AlreadyDeployed => (StatusCode::CONFLICT, "already_deployed"),
+            Error::Other(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+        };
+
+        (status, Json(ErrorResponse { error, message })).into_response()
+    }
+}
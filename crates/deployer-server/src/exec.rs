@@ -0,0 +1,111 @@
+use bollard::query_parameters::{CreateExecOptions, LogsOptionsBuilder, StartExecOptions};
+use deployer_common::challenge::DeployableContext;
+use eyre::eyre;
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+
+/// One line of container log output, demultiplexed from Docker's TTY frame format.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    pub stream: &'static str,
+    pub message: String,
+}
+
+/// Tails `container_name`'s stdout/stderr, demultiplexing into [`LogLine`]s as they arrive.
+pub fn stream_logs(
+    ctx: &DeployableContext,
+    container_name: &str,
+    follow: bool,
+    tail: &str,
+) -> impl Stream<Item = eyre::Result<LogLine>> + Send + 'static {
+    let options = LogsOptionsBuilder::new()
+        .follow(follow)
+        .stdout(true)
+        .stderr(true)
+        .tail(tail)
+        .build();
+
+    ctx.docker.logs(container_name, Some(options)).map(|res| {
+        res.map(|chunk| {
+            use bollard::container::LogOutput;
+            match chunk {
+                LogOutput::StdOut { message } => LogLine {
+                    stream: "stdout",
+                    message: String::from_utf8_lossy(&message).into_owned(),
+                },
+                LogOutput::StdErr { message } => LogLine {
+                    stream: "stderr",
+                    message: String::from_utf8_lossy(&message).into_owned(),
+                },
+                LogOutput::Console { message } | LogOutput::StdIn { message } => LogLine {
+                    stream: "console",
+                    message: String::from_utf8_lossy(&message).into_owned(),
+                },
+            }
+        })
+        .map_err(eyre::Error::from)
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExecResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i64>,
+}
+
+/// Runs a one-shot command in a running container via the create-exec/start-exec pair and
+/// collects the demultiplexed output plus the exit code.
+pub async fn run_exec(
+    ctx: &DeployableContext,
+    container_name: &str,
+    cmd: Vec<String>,
+) -> eyre::Result<ExecResult> {
+    let exec = ctx
+        .docker
+        .create_exec(
+            container_name,
+            CreateExecOptions {
+                cmd: Some(cmd),
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+
+    match ctx
+        .docker
+        .start_exec(&exec.id, Some(StartExecOptions::default()))
+        .await?
+    {
+        bollard::exec::StartExecResults::Attached { mut output, .. } => {
+            use bollard::container::LogOutput;
+            while let Some(chunk) = output.next().await {
+                match chunk? {
+                    LogOutput::StdOut { message } => {
+                        stdout.push_str(&String::from_utf8_lossy(&message))
+                    }
+                    LogOutput::StdErr { message } => {
+                        stderr.push_str(&String::from_utf8_lossy(&message))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        bollard::exec::StartExecResults::Detached => {
+            return Err(eyre!("exec unexpectedly ran detached"));
+        }
+    }
+
+    let inspected = ctx.docker.inspect_exec(&exec.id).await?;
+
+    Ok(ExecResult {
+        stdout,
+        stderr,
+        exit_code: inspected.exit_code,
+    })
+}
@@ -6,11 +6,16 @@ use sqlx::postgres::PgPoolOptions;
 mod api;
 mod config;
 mod deploy;
+mod deployment_jobs;
 mod error;
+mod exec;
+mod metrics;
+mod ports;
+mod readiness;
+mod stats_monitor;
 
 use config::State;
 use error::Result;
-use log::error;
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 #[tokio::main]
@@ -42,36 +47,40 @@ async fn main() -> eyre::Result<()> {
         db: pool.clone(),
         challenge_data: challs.into(),
         tasks: tt.clone(),
+        caddy_clients: Default::default(),
+        live_stats: Default::default(),
     });
 
-    let inherited_containers = sqlx::query_as!(
-        api::ChallengeDeploymentRow,
-        "SELECT * FROM challenge_deployments WHERE destroyed_at IS NULL AND expired_at IS NOT NULL"
+    // any instanced deployments that are expired but were never enqueued (e.g. rows left over
+    // from before deployment_jobs existed) get backfilled so a worker picks them up too
+    let orphaned_expirations = sqlx::query!(
+        r#"SELECT cd.id, cd.expired_at AS "expired_at!" FROM challenge_deployments cd
+           LEFT JOIN deployment_jobs dj ON dj.deployment_id = cd.id AND dj.kind = 'expire'
+           WHERE cd.destroyed_at IS NULL AND cd.expired_at IS NOT NULL AND dj.id IS NULL"#
     )
     .fetch_all(&pool)
     .await?;
-    for container in inherited_containers {
-        let container_id = container.id;
-        if let Ok(container) = TryInto::<deploy::ChallengeDeployment>::try_into(container) {
-            let expiration_time = container.expired_at.unwrap();
-            let dur = (expiration_time - chrono::Utc::now().naive_utc())
-                .max(chrono::TimeDelta::zero())
-                .to_std()
-                .unwrap();
-
-            let state_clone = state.clone();
-            tokio::spawn(async move {
-                tokio::time::sleep(dur).await;
-                deploy::destroy_challenge_task(state_clone, container).await;
-            });
-        } else {
-            error!(
-                "failed to start cleanup task for deployment {}",
-                container_id
-            );
-        }
+    for row in orphaned_expirations {
+        sqlx::query!(
+            "INSERT INTO deployment_jobs (kind, deployment_id, run_at) VALUES ('expire', $1, $2)",
+            row.id,
+            row.expired_at,
+        )
+        .execute(&pool)
+        .await?;
+    }
+
+    // a small pool of workers, so one slow deploy/destroy doesn't stall every other job
+    let worker_count: usize = std::env::var("DEPLOYMENT_JOB_WORKERS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3);
+    for _ in 0..worker_count {
+        tt.spawn(deployment_jobs::worker_loop(state.clone()));
     }
 
+    tt.spawn(stats_monitor::poll_loop(state.clone()));
+
     let app = Router::<State>::new()
         .nest("/api", api::router())
         .with_state(state);
@@ -0,0 +1,134 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Registry, TextEncoder,
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
+    register_int_gauge_vec_with_registry, register_int_gauge_with_registry,
+};
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+pub static DEPLOY_ATTEMPTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec_with_registry!(
+        "deployer_deploy_attempts_total",
+        "Deploy attempts, labeled by challenge slug and strategy",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static DEPLOY_SUCCESSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec_with_registry!(
+        "deployer_deploy_successes_total",
+        "Successful deploys, labeled by challenge slug and strategy",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static DEPLOY_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec_with_registry!(
+        "deployer_deploy_failures_total",
+        "Failed deploys, labeled by challenge slug and strategy",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static DESTROY_ATTEMPTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec_with_registry!(
+        "deployer_destroy_attempts_total",
+        "Destroy attempts, labeled by challenge slug and strategy",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static DESTROY_SUCCESSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec_with_registry!(
+        "deployer_destroy_successes_total",
+        "Successful destroys, labeled by challenge slug and strategy",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static DESTROY_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec_with_registry!(
+        "deployer_destroy_failures_total",
+        "Failed destroys, labeled by challenge slug and strategy",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static LIVE_DEPLOYMENTS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec_with_registry!(
+        "deployer_live_deployments",
+        "Currently-live deployments, labeled by host keychain",
+        &["host"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static LIVE_CONTAINERS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec_with_registry!(
+        "deployer_live_containers",
+        "Currently-live containers, labeled by host keychain",
+        &["host"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static DEPLOY_LATENCY_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec_with_registry!(
+        "deployer_deploy_latency_seconds",
+        "End-to-end deploy_challenge latency",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static DESTROY_LATENCY_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec_with_registry!(
+        "deployer_destroy_latency_seconds",
+        "End-to-end destroy_challenge latency",
+        &["challenge", "strategy"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static GUARD_ROLLBACKS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec_with_registry!(
+        "deployer_guard_rollbacks_total",
+        "Times a DockerGuard/CaddyGuard actually tore something down on an uncommitted deploy",
+        &["guard"],
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub static PENDING_EXPIRATIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge_with_registry!(
+        "deployer_pending_expirations",
+        "Pending expire jobs in deployment_jobs",
+        REGISTRY
+    )
+    .unwrap()
+});
+
+pub fn render() -> String {
+    let metric_families = REGISTRY.gather();
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&metric_families, &mut buf).ok();
+    String::from_utf8(buf).unwrap_or_default()
+}
@@ -0,0 +1,117 @@
+use crate::deploy::{calculate_static_tcp_port, get_unused_port};
+
+/// A host TCP port reserved in `port_allocations`. Dropping this without calling anything just
+/// leaves the row in place; release it explicitly via [`release`], which is what
+/// `DockerGuard::adrop` does on an uncommitted deploy.
+#[derive(Debug, Clone, Copy)]
+pub struct PortReservation {
+    pub id: i32,
+    pub port: u16,
+    /// False when this reservation was reused from a prior static deploy - callers should only
+    /// release reservations they actually created, so a failed redeploy doesn't tear down a
+    /// stable static port mapping that was already live.
+    pub freshly_created: bool,
+}
+
+/// Allocates a host port for `container`'s `container_port`, persisting the assignment so
+/// redeploys are stable and two challenges never map to the same host port.
+///
+/// Static challenges (`team_id: None`) reuse a prior allocation if one exists, and otherwise
+/// probe the deterministic `calculate_static_tcp_port` candidate, walking a deterministic rehash
+/// sequence (bumping the hash seed) on collision until a free port is found. Instanced challenges
+/// (`team_id: Some(_)`) reserve a fresh ephemeral port each time, inside `tx`, so concurrent
+/// deploys on the same host can't race each other onto the same port.
+pub async fn allocate_tcp_port(
+    tx: &mut sqlx::PgTransaction<'_>,
+    host: &str,
+    chall_id: &str,
+    container: &str,
+    container_port: u16,
+    team_id: Option<i32>,
+) -> eyre::Result<PortReservation> {
+    if team_id.is_none() {
+        if let Some(existing) = sqlx::query!(
+            "SELECT id, host_port FROM port_allocations
+             WHERE host = $1 AND chall_id = $2 AND container = $3 AND container_port = $4 AND team_id IS NULL",
+            host,
+            chall_id,
+            container,
+            container_port as i32,
+        )
+        .fetch_optional(&mut **tx)
+        .await?
+        {
+            return Ok(PortReservation {
+                id: existing.id,
+                port: existing.host_port as u16,
+                freshly_created: false,
+            });
+        }
+    }
+
+    for bump in 0..=u16::MAX as u64 {
+        let candidate = match team_id {
+            None => calculate_static_tcp_port(chall_id, container, container_port, bump),
+            Some(_) => get_unused_port(),
+        };
+
+        let inserted = sqlx::query!(
+            "INSERT INTO port_allocations (host, chall_id, container, container_port, host_port, team_id)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (host, host_port) DO NOTHING
+             RETURNING id",
+            host,
+            chall_id,
+            container,
+            container_port as i32,
+            candidate as i32,
+            team_id,
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        if let Some(row) = inserted {
+            return Ok(PortReservation {
+                id: row.id,
+                port: candidate,
+                freshly_created: true,
+            });
+        }
+    }
+
+    Err(eyre::eyre!(
+        "exhausted the rehash walk allocating a host port for {}/{}",
+        chall_id,
+        container
+    ))
+}
+
+/// Releases a reservation, e.g. because the deploy it was made for got rolled back.
+pub async fn release(pool: &sqlx::PgPool, id: i32) {
+    sqlx::query!("DELETE FROM port_allocations WHERE id = $1", id)
+        .execute(pool)
+        .await
+        .ok();
+}
+
+/// Releases every instanced reservation made for `(host, chall_id, container, team_id)`, called
+/// when an instanced deployment is destroyed. Static allocations are left alone so a redeploy
+/// keeps the same host port.
+pub async fn release_instanced(
+    pool: &sqlx::PgPool,
+    host: &str,
+    chall_id: &str,
+    container: &str,
+    team_id: i32,
+) {
+    sqlx::query!(
+        "DELETE FROM port_allocations WHERE host = $1 AND chall_id = $2 AND container = $3 AND team_id = $4",
+        host,
+        chall_id,
+        container,
+        team_id,
+    )
+    .execute(pool)
+    .await
+    .ok();
+}
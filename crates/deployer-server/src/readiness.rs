@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use bollard::query_parameters::LogsOptionsBuilder;
+use deployer_common::challenge::{DeployableContext, ReadinessProbe};
+use eyre::eyre;
+use futures_util::StreamExt;
+use log::debug;
+use regex::Regex;
+
+/// Awaits the configured readiness probe for a just-started container, returning an error on
+/// timeout so the caller's rollback guards fire exactly as they would for any other deploy error.
+pub async fn await_readiness(
+    probe: &ReadinessProbe,
+    ctx: &DeployableContext,
+    container_name: &str,
+    container_ip: &str,
+) -> eyre::Result<()> {
+    match probe {
+        ReadinessProbe::Tcp {
+            port,
+            timeout_secs,
+            interval_ms,
+        } => await_tcp(container_ip, *port, *timeout_secs, *interval_ms).await,
+        ReadinessProbe::Http {
+            port,
+            path,
+            accepted_statuses,
+            timeout_secs,
+            interval_ms,
+        } => await_http(container_ip, *port, path, accepted_statuses, *timeout_secs, *interval_ms).await,
+        ReadinessProbe::Log {
+            regex,
+            timeout_secs,
+        } => await_log(ctx, container_name, regex, *timeout_secs).await,
+    }
+}
+
+async fn await_tcp(ip: &str, port: u16, timeout_secs: u64, interval_ms: u64) -> eyre::Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    loop {
+        if tokio::net::TcpStream::connect((ip, port)).await.is_ok() {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(eyre!(
+                "readiness probe timed out waiting for tcp connect to {}:{}",
+                ip,
+                port
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+    }
+}
+
+async fn await_http(
+    ip: &str,
+    port: u16,
+    path: &str,
+    accepted_statuses: &[u16],
+    timeout_secs: u64,
+    interval_ms: u64,
+) -> eyre::Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("http://{}:{}{}", ip, port, path);
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    loop {
+        if let Ok(resp) = client.get(&url).send().await {
+            if accepted_statuses.contains(&resp.status().as_u16()) {
+                return Ok(());
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(eyre!(
+                "readiness probe timed out waiting for http {} to return one of {:?}",
+                url,
+                accepted_statuses
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+    }
+}
+
+async fn await_log(
+    ctx: &DeployableContext,
+    container_name: &str,
+    pattern: &str,
+    timeout_secs: u64,
+) -> eyre::Result<()> {
+    let re = Regex::new(pattern)?;
+    let options = LogsOptionsBuilder::new()
+        .follow(true)
+        .stdout(true)
+        .stderr(true)
+        .tail("0")
+        .build();
+
+    let mut stream = ctx.docker.logs(container_name, Some(options));
+    let fut = async {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            let line = chunk.to_string();
+            debug!("readiness log probe saw: {}", line);
+            if re.is_match(&line) {
+                return Ok(());
+            }
+        }
+        Err(eyre!(
+            "container {} log stream ended before readiness regex matched",
+            container_name
+        ))
+    };
+
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), fut).await {
+        Ok(res) => res,
+        Err(_) => Err(eyre!(
+            "readiness probe timed out waiting for log regex {:?} on {}",
+            pattern,
+            container_name
+        )),
+    }
+}
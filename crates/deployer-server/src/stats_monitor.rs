@@ -0,0 +1,259 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use bollard::query_parameters::StatsOptionsBuilder;
+use chrono::{NaiveDateTime, Utc};
+use deployer_common::challenge::{DeployableContext, DeploymentStrategy};
+use futures_util::StreamExt;
+use log::{debug, error, warn};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::{State, api::ChallengeDeploymentRow, deploy::ChallengeDeployment};
+
+/// A CPU/memory sample for one live instanced container, as last observed by its watcher.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveStats {
+    pub deployment_id: i32,
+    pub public_id: String,
+    pub team_id: Option<i32>,
+    pub challenge_id: i32,
+    pub container: String,
+    pub cpu_percent: f64,
+    pub mem_usage_bytes: i64,
+    pub mem_limit_bytes: Option<i64>,
+    pub consecutive_over_threshold: u32,
+    pub sampled_at: NaiveDateTime,
+}
+
+/// Scans for running `Instanced` deployments and starts a stats watcher for any container that
+/// doesn't already have one, so an instance created before this loop last looked (or before this
+/// process last restarted) still gets picked up within one scan interval.
+pub async fn poll_loop(state: State) {
+    let watched: Arc<Mutex<HashSet<String>>> = Default::default();
+    let interval = Duration::from_secs(state.config.stats_scan_interval_secs);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let rows = match sqlx::query_as!(
+            ChallengeDeploymentRow,
+            "SELECT * FROM challenge_deployments WHERE deployed = true AND destroyed_at IS NULL"
+        )
+        .fetch_all(&state.db)
+        .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Failed to scan deployments for stats monitoring: {:?}", e);
+                continue;
+            }
+        };
+
+        for row in rows {
+            let deployment: ChallengeDeployment = match row.try_into() {
+                Ok(d) => d,
+                Err(e) => {
+                    error!("Failed to decode deployment row for stats monitoring: {:?}", e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = start_watchers_for(&state, &deployment, &watched).await {
+                error!(
+                    "Failed to start stats watchers for deployment {}: {:?}",
+                    deployment.public_id, e
+                );
+            }
+        }
+    }
+}
+
+async fn start_watchers_for(
+    state: &State,
+    deployment: &ChallengeDeployment,
+    watched: &Arc<Mutex<HashSet<String>>>,
+) -> eyre::Result<()> {
+    let Some(data) = &deployment.data else {
+        return Ok(());
+    };
+
+    let chall_public_id = sqlx::query_scalar!(
+        "SELECT public_id FROM challenges WHERE id = $1",
+        deployment.challenge_id
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    let chall_data = {
+        let rg = state.challenge_data.read().await;
+        rg.get(&chall_public_id).cloned()
+    };
+    let Some(chall_data) = chall_data else {
+        return Ok(());
+    };
+
+    if !matches!(chall_data.strategy, DeploymentStrategy::Instanced) {
+        return Ok(());
+    }
+
+    let Some(containers) = &chall_data.container else {
+        return Ok(());
+    };
+
+    let host_keychain =
+        &state.config.host_keychains[chall_data.host.as_deref().unwrap_or("default")];
+    let ctx: DeployableContext = host_keychain.docker.clone().try_into()?;
+
+    for (ct, container_data) in data {
+        let Some(chall_container) = containers.get(ct) else {
+            continue;
+        };
+
+        let mut wg = watched.lock().await;
+        if !wg.insert(container_data.container_id.clone()) {
+            continue;
+        }
+        drop(wg);
+
+        state.tasks.spawn(watch_instance(
+            state.clone(),
+            ctx.clone(),
+            deployment.clone(),
+            ct.clone(),
+            container_data.container_id.clone(),
+            chall_container.limits.mem,
+            watched.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn watch_instance(
+    state: State,
+    ctx: DeployableContext,
+    deployment: ChallengeDeployment,
+    container_key: String,
+    container_id: String,
+    mem_limit_bytes: Option<i64>,
+    watched: Arc<Mutex<HashSet<String>>>,
+) {
+    if let Err(e) = run_watch(
+        &state,
+        &ctx,
+        &deployment,
+        &container_key,
+        &container_id,
+        mem_limit_bytes,
+    )
+    .await
+    {
+        debug!("stats watcher for container {} ended: {:?}", container_id, e);
+    }
+
+    state.live_stats.write().await.remove(&container_id);
+    watched.lock().await.remove(&container_id);
+}
+
+async fn run_watch(
+    state: &State,
+    ctx: &DeployableContext,
+    deployment: &ChallengeDeployment,
+    container_key: &str,
+    container_id: &str,
+    mem_limit_bytes: Option<i64>,
+) -> eyre::Result<()> {
+    let options = StatsOptionsBuilder::new().stream(true).build();
+    let mut stats = ctx.docker.stats(container_id, Some(options));
+
+    let mut consecutive_over_threshold = 0u32;
+
+    while let Some(sample) = stats.next().await {
+        let sample = sample?;
+
+        let cpu_percent = cpu_percent(&sample);
+        let mem_usage_bytes = sample.memory_stats.usage.unwrap_or(0) as i64;
+
+        let over_threshold = cpu_percent >= state.config.stats_cpu_threshold_percent
+            || mem_limit_bytes.is_some_and(|limit| {
+                limit > 0
+                    && (mem_usage_bytes as f64 / limit as f64) * 100.0
+                        >= state.config.stats_mem_threshold_percent
+            });
+
+        consecutive_over_threshold = if over_threshold {
+            consecutive_over_threshold + 1
+        } else {
+            0
+        };
+
+        state.live_stats.write().await.insert(
+            container_id.to_owned(),
+            LiveStats {
+                deployment_id: deployment.id,
+                public_id: deployment.public_id.clone(),
+                team_id: deployment.team_id,
+                challenge_id: deployment.challenge_id,
+                container: container_key.to_owned(),
+                cpu_percent,
+                mem_usage_bytes,
+                mem_limit_bytes,
+                consecutive_over_threshold,
+                sampled_at: Utc::now().naive_utc(),
+            },
+        );
+
+        if consecutive_over_threshold >= state.config.stats_consecutive_samples {
+            warn!(
+                "deployment {} container {} exceeded resource thresholds for {} consecutive samples ({:.1}% cpu, {} bytes mem) - auto-killing",
+                deployment.public_id, container_key, consecutive_over_threshold, cpu_percent, mem_usage_bytes
+            );
+
+            sqlx::query!(
+                "INSERT INTO resource_abuse_events (deployment_id, challenge_id, team_id, container, cpu_percent, mem_usage_bytes, mem_limit_bytes) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                deployment.id,
+                deployment.challenge_id,
+                deployment.team_id,
+                container_key,
+                cpu_percent,
+                mem_usage_bytes,
+                mem_limit_bytes,
+            )
+            .execute(&state.db)
+            .await?;
+
+            let mut tx = state.db.begin().await?;
+            crate::deployment_jobs::enqueue_destroy(&mut tx, deployment.id).await?;
+            tx.commit().await?;
+
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Docker's documented CPU-percent formula: the delta in the container's own CPU usage over the
+/// delta in the whole system's CPU usage, scaled by the number of CPUs available to it.
+fn cpu_percent(stats: &bollard::container::Stats) -> f64 {
+    let cpu_delta =
+        stats.cpu_stats.cpu_usage.total_usage as f64 - stats.precpu_stats.cpu_usage.total_usage as f64;
+    let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) as f64
+        - stats.precpu_stats.system_cpu_usage.unwrap_or(0) as f64;
+
+    if cpu_delta <= 0.0 || system_delta <= 0.0 {
+        return 0.0;
+    }
+
+    let online_cpus = stats.cpu_stats.online_cpus.unwrap_or_else(|| {
+        stats
+            .cpu_stats
+            .cpu_usage
+            .percpu_usage
+            .as_ref()
+            .map(|v| v.len() as u64)
+            .unwrap_or(1)
+    }) as f64;
+
+    (cpu_delta / system_delta) * online_cpus * 100.0
+}